@@ -0,0 +1,225 @@
+// Bots served over HTTP with the Battlesnake API.
+// The server gets `/start` before the first move, `/move` every tick and `/end` once the game is over.
+//
+// Battlesnake counts y upwards from the bottom row while Rusty Snake counts it downwards, so every
+// point is flipped on the way out. `up` then means the same on both sides. There is no health in
+// Rusty Snake, so it is always 100. Battlesnake hazards can be walked through, so the block and the
+// walls of a level are sent as the body of an extra snake, `walls`, with no health left.
+// Wrapping boards are played under the `wrapped` ruleset.
+
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use crate::game::{Body, Direction, Point, View};
+use crate::strategy::Strategy;
+
+#[derive(Serialize)]
+struct GameRequest {
+    game: GameInfo,
+    turn: u64,
+    board: Board,
+    you: Battlesnake,
+}
+
+#[derive(Serialize, Clone)]
+struct GameInfo {
+    id: String,
+    ruleset: Ruleset,
+    map: &'static str,
+    timeout: u64,
+    source: &'static str,
+}
+
+#[derive(Serialize, Clone)]
+struct Ruleset {
+    name: &'static str,
+    version: &'static str,
+}
+
+#[derive(Serialize)]
+struct Board {
+    width: i32,
+    height: i32,
+    food: Vec<Coord>,
+    hazards: Vec<Coord>,
+    snakes: Vec<Battlesnake>,
+}
+
+#[derive(Serialize, Clone)]
+struct Battlesnake {
+    id: String,
+    name: String,
+    health: i32,
+    body: Vec<Coord>,
+    latency: String,
+    head: Coord,
+    length: usize,
+    shout: String,
+}
+
+#[derive(Serialize, Clone, Copy)]
+struct Coord {
+    x: i32,
+    y: i32,
+}
+
+#[derive(Deserialize)]
+struct MoveResponse {
+    #[serde(rename = "move")]
+    dir: String,
+}
+
+pub struct BattlesnakeClient {
+    url: String,
+    agent: ureq::Agent,
+    game: GameInfo,
+    turn: u64,
+    started: bool,
+}
+
+impl BattlesnakeClient {
+    // Checks that a server answers at `url` before any game starts
+    pub fn connect(url: &str, timeout: Duration) -> Result<BattlesnakeClient, Box<ureq::Error>> {
+        let url = url.trim_end_matches('/').to_string();
+        let agent = ureq::AgentBuilder::new().timeout(timeout).build();
+        agent.get(&format!("{}/", url)).call()?;
+        let game = GameInfo {
+            id: format!("rusty_snake-{:08x}", rand::random::<u32>()),
+            ruleset: Ruleset { name: "standard", version: env!("CARGO_PKG_VERSION") },
+            map: "standard",
+            timeout: timeout.as_millis() as u64,
+            source: "custom",
+        };
+        Ok(BattlesnakeClient { url, agent, game, turn: 0, started: false })
+    }
+
+    fn request(&self, view: &View) -> GameRequest {
+        let coord = |p: Point| Coord { x: p.x, y: view.height - 1 - p.y };
+        let snake = |id: String, health: i32, body: Vec<Coord>| Battlesnake {
+            name: id.clone(),
+            id,
+            health,
+            head: body[0],
+            length: body.len(),
+            body,
+            latency: "0".to_string(),
+            shout: String::new(),
+        };
+        let body = |body: &Body| body.iter().map(|p| coord(*p)).collect();
+        let you = snake("you".to_string(), 100, body(view.snake));
+        let mut snakes = vec![you.clone()];
+        for (i, opponent) in view.opponents.iter().enumerate().filter(|(_, o)| !o.snake.is_empty()) {
+            snakes.push(snake(format!("opponent-{}", i + 1), 100, body(opponent.snake)));
+        }
+        // Never moves, the block comes first as the closest thing to a head
+        snakes.push(snake("walls".to_string(), 0, [view.block].iter().chain(view.board.walls()).map(|p| coord(*p)).collect()));
+        let mut game = self.game.clone();
+        if view.board.wraps() {
+            game.ruleset.name = "wrapped";
+        }
+        GameRequest {
+            game,
+            turn: self.turn,
+            board: Board {
+                width: view.width,
+                height: view.height,
+                food: vec![coord(view.food)],
+                hazards: vec![],
+                snakes,
+            },
+            you,
+        }
+    }
+
+    fn post(&self, path: &str, view: &View) -> Result<ureq::Response, Box<ureq::Error>> {
+        Ok(self.agent.post(&format!("{}{}", self.url, path)).send_json(self.request(view))?)
+    }
+}
+
+impl Strategy for BattlesnakeClient {
+    fn name(&self) -> String {
+        self.url.clone()
+    }
+
+    fn choose(&mut self, view: &View) -> Option<Direction> {
+        if !self.started {
+            self.started = true;
+            if let Err(e) = self.post("/start", view) {
+                eprintln!("rusty_snake: {}/start failed: {}", self.url, e);
+            }
+        }
+        let response = self.post("/move", view);
+        self.turn += 1;
+        match response.and_then(|response| response.into_json::<MoveResponse>().map_err(|e| Box::new(e.into()))) {
+            Ok(response) => {
+                let dir = match response.dir.as_str() {
+                    "up" => Some(Direction::Up),
+                    "down" => Some(Direction::Down),
+                    "left" => Some(Direction::Left),
+                    "right" => Some(Direction::Right),
+                    _ => None,
+                };
+                if dir.is_none() {
+                    eprintln!("rusty_snake: {}/move answered {:?}, expected a move", self.url, response.dir);
+                }
+                dir
+            },
+            // Like the Battlesnake engine, a snake without an answer carries on straight ahead
+            Err(e) => {
+                eprintln!("rusty_snake: {}/move failed: {}", self.url, e);
+                Some(view.heading)
+            },
+        }
+    }
+
+    fn finish(&mut self, view: &View) {
+        if self.started {
+            if let Err(e) = self.post("/end", view) {
+                eprintln!("rusty_snake: {}/end failed: {}", self.url, e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use crate::game::{Game, Mode, Seat};
+    use crate::level::Level;
+    use crate::settings::Settings;
+
+    #[test]
+    fn walls_and_the_block_are_sent_as_a_snake_that_cannot_move() {
+        let level = Arc::new(Level::find("box").unwrap());
+        let settings = level.settings(&Settings::default());
+        let game = Game::on_level(Mode::Competitive, 1, &settings, level.clone());
+        let view = game.view(Seat::PLAYER, None);
+        let client = BattlesnakeClient {
+            url: "http://localhost".to_string(),
+            agent: ureq::agent(),
+            game: GameInfo {
+                id: "test".to_string(),
+                ruleset: Ruleset { name: "standard", version: "test" },
+                map: "standard",
+                timeout: 500,
+                source: "custom",
+            },
+            turn: 0,
+            started: false,
+        };
+        let request = serde_json::to_value(client.request(&view)).unwrap();
+        let board = &request["board"];
+        assert_eq!(board["hazards"], serde_json::json!([]));
+        let snakes = board["snakes"].as_array().unwrap();
+        assert_eq!(snakes.len(), 3);
+        let walls = &snakes[2];
+        assert_eq!(walls["id"], "walls");
+        assert_eq!(walls["health"], 0);
+        assert_eq!(walls["length"], level.walls.len() + 1);
+        let block = serde_json::json!({ "x": view.block.x, "y": view.height - 1 - view.block.y });
+        assert_eq!(walls["head"], block);
+        assert_eq!(walls["body"][0], block);
+        // The top left corner of the box is at the top of the board, so its y is the largest
+        assert!(walls["body"].as_array().unwrap().contains(&serde_json::json!({ "x": 0, "y": view.height - 1 })));
+    }
+}
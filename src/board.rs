@@ -0,0 +1,227 @@
+// Occupancy of the board cells.
+// Tracks the walls and which snake covers each cell, and keeps a set of the free ones, so collision
+// checks and spawns take the same time however long the snakes grow.
+// The board also knows its shape: on a wrapping board a snake leaving one edge comes back in on
+// the opposite one, which changes what counts as a neighbour and how far apart two cells are.
+
+use rand::Rng;
+use crate::game::{Direction, Point, Seat};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Cell {
+    Free,
+    Wall,
+    Snake(Seat),
+}
+
+#[derive(Clone, Debug)]
+pub struct Board {
+    width: i32,
+    height: i32,
+    wrap: bool,
+    // What covers each cell, indexed by y * width + x
+    cells: Vec<Cell>,
+    walls: Vec<Point>,
+    // Indices of the free cells in no particular order
+    free: Vec<usize>,
+    // Position of every free cell in `free`, NOT_FREE for covered cells
+    slots: Vec<usize>,
+}
+
+const NOT_FREE: usize = usize::MAX;
+
+impl Board {
+    pub fn new(width: i32, height: i32, wrap: bool) -> Board {
+        let size = (width * height) as usize;
+        Board {
+            width,
+            height,
+            wrap,
+            cells: vec![Cell::Free; size],
+            walls: vec![],
+            free: (0..size).collect(),
+            slots: (0..size).collect(),
+        }
+    }
+
+    pub fn width(&self) -> i32 {
+        self.width
+    }
+
+    pub fn height(&self) -> i32 {
+        self.height
+    }
+
+    pub fn wraps(&self) -> bool {
+        self.wrap
+    }
+
+    pub fn in_bounds(&self, p: Point) -> bool {
+        p.x >= 0 && p.x < self.width && p.y >= 0 && p.y < self.height
+    }
+
+    // The cell one move away from `p`. Off the board unless the board wraps.
+    pub fn step(&self, p: Point, dir: Direction) -> Point {
+        let next = p.step(dir);
+        if self.wrap {
+            Point { x: next.x.rem_euclid(self.width), y: next.y.rem_euclid(self.height) }
+        } else {
+            next
+        }
+    }
+
+    // Shortest move from `from` to `to` along each axis, which may cross an edge when the board wraps
+    pub fn offset(&self, from: Point, to: Point) -> Point {
+        let shortest = |delta: i32, size: i32| {
+            if !self.wrap {
+                delta
+            } else if delta > size / 2 {
+                delta - size
+            } else if delta < -size / 2 {
+                delta + size
+            } else {
+                delta
+            }
+        };
+        Point { x: shortest(to.x - from.x, self.width), y: shortest(to.y - from.y, self.height) }
+    }
+
+    // Number of moves between two cells on an empty board
+    pub fn distance(&self, a: Point, b: Point) -> i32 {
+        let offset = self.offset(a, b);
+        offset.x.abs() + offset.y.abs()
+    }
+
+    // Direction that leads from `from` to a neighbouring cell
+    pub fn direction_to(&self, from: Point, to: Point) -> Option<Direction> {
+        Direction::ALL.into_iter().find(|dir| self.step(from, *dir) == to)
+    }
+
+    fn index(&self, p: Point) -> usize {
+        (p.y * self.width + p.x) as usize
+    }
+
+    fn point(&self, index: usize) -> Point {
+        Point { x: index as i32 % self.width, y: index as i32 / self.width }
+    }
+
+    // What covers the cell, cells off the board count as free
+    pub fn cell(&self, p: Point) -> Cell {
+        if self.in_bounds(p) {
+            self.cells[self.index(p)]
+        } else {
+            Cell::Free
+        }
+    }
+
+    // Snake covering the cell, `None` for free cells, walls and cells off the board
+    pub fn occupant(&self, p: Point) -> Option<Seat> {
+        match self.cell(p) {
+            Cell::Snake(seat) => Some(seat),
+            _ => None,
+        }
+    }
+
+    pub fn is_wall(&self, p: Point) -> bool {
+        self.cell(p) == Cell::Wall
+    }
+
+    // True when a snake or a wall covers the cell
+    pub fn is_occupied(&self, p: Point) -> bool {
+        self.cell(p) != Cell::Free
+    }
+
+    pub fn walls(&self) -> &[Point] {
+        &self.walls
+    }
+
+    pub fn free_count(&self) -> usize {
+        self.free.len()
+    }
+
+    pub fn occupy(&mut self, p: Point, seat: Seat) {
+        self.cover(p, Cell::Snake(seat));
+    }
+
+    // Walls go up before any snake moves and stay for the whole game
+    pub fn add_wall(&mut self, p: Point) {
+        if self.cell(p) != Cell::Wall {
+            self.cover(p, Cell::Wall);
+            self.walls.push(p);
+        }
+    }
+
+    fn cover(&mut self, p: Point, cell: Cell) {
+        let index = self.index(p);
+        self.cells[index] = cell;
+        let slot = self.slots[index];
+        if slot != NOT_FREE {
+            self.free.swap_remove(slot);
+            if let Some(&moved) = self.free.get(slot) {
+                self.slots[moved] = slot;
+            }
+            self.slots[index] = NOT_FREE;
+        }
+    }
+
+    pub fn vacate(&mut self, p: Point) {
+        let index = self.index(p);
+        self.cells[index] = Cell::Free;
+        if self.slots[index] == NOT_FREE {
+            self.slots[index] = self.free.len();
+            self.free.push(index);
+        }
+    }
+
+    // A free cell picked uniformly at random, other than `exclude`
+    pub fn random_free(&self, rng: &mut impl Rng, exclude: Option<Point>) -> Option<Point> {
+        let excluded_slot = exclude.filter(|p| self.in_bounds(*p)).map(|p| self.slots[self.index(p)]).filter(|slot| *slot != NOT_FREE);
+        let choices = self.free.len() - usize::from(excluded_slot.is_some());
+        if choices == 0 {
+            return None;
+        }
+        let mut slot = rng.gen_range(0..choices);
+        if excluded_slot.is_some_and(|excluded| slot >= excluded) {
+            slot += 1;
+        }
+        Some(self.point(self.free[slot]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn p(x: i32, y: i32) -> Point {
+        Point { x, y }
+    }
+
+    #[test]
+    fn a_wrapping_board_steps_across_its_edges() {
+        let board = Board::new(6, 4, true);
+        assert_eq!(board.step(p(5, 2), Direction::Right), p(0, 2));
+        assert_eq!(board.step(p(0, 2), Direction::Left), p(5, 2));
+        assert_eq!(board.step(p(3, 0), Direction::Up), p(3, 3));
+        assert_eq!(board.step(p(3, 3), Direction::Down), p(3, 0));
+        assert_eq!(board.direction_to(p(0, 0), p(5, 0)), Some(Direction::Left));
+
+        let board = Board::new(6, 4, false);
+        assert_eq!(board.step(p(5, 2), Direction::Right), p(6, 2));
+        assert!(!board.in_bounds(board.step(p(3, 0), Direction::Up)));
+        assert_eq!(board.direction_to(p(0, 0), p(5, 0)), None);
+    }
+
+    #[test]
+    fn a_wrapping_board_measures_the_short_way_around() {
+        let board = Board::new(6, 4, true);
+        assert_eq!(board.distance(p(0, 0), p(5, 0)), 1);
+        assert_eq!(board.distance(p(0, 0), p(3, 2)), 5);
+        assert_eq!(board.distance(p(1, 0), p(4, 3)), 4);
+        assert_eq!(board.offset(p(0, 0), p(5, 3)), p(-1, -1));
+        assert_eq!(board.offset(p(5, 3), p(0, 0)), p(1, 1));
+
+        let board = Board::new(6, 4, false);
+        assert_eq!(board.distance(p(0, 0), p(5, 0)), 5);
+        assert_eq!(board.offset(p(0, 0), p(5, 3)), p(5, 3));
+    }
+}
@@ -0,0 +1,707 @@
+// Pathfinding for the Rusty Snake bots.
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashSet, VecDeque};
+use std::fmt;
+use crate::board::Board;
+use crate::game::{Body, Direction, Point};
+
+// Shortest path from `start` to `goal` across the board, found with A*.
+// Cells for which `blocked` returns true are never entered.
+// The path leaves out `start` and ends on `goal`, so its first point is the next move.
+pub fn astar(board: &Board, start: Point, goal: Point, blocked: impl Fn(Point) -> bool) -> Option<Vec<Point>> {
+    if !board.in_bounds(start) || !board.in_bounds(goal) {
+        return None;
+    }
+    if start == goal {
+        return Some(vec![]);
+    }
+    let width = board.width();
+    let index = |p: Point| (p.y * width + p.x) as usize;
+    let size = (width * board.height()) as usize;
+    let mut cost = vec![i32::MAX; size];
+    let mut came_from: Vec<Option<Point>> = vec![None; size];
+    // Ordered by estimated total length, ties go to the cell closest to the goal
+    let mut open = BinaryHeap::new();
+
+    cost[index(start)] = 0;
+    open.push(Reverse((board.distance(start, goal), board.distance(start, goal), start.x, start.y)));
+    while let Some(Reverse((_, _, x, y))) = open.pop() {
+        let current = Point { x, y };
+        if current == goal {
+            let mut path = vec![goal];
+            let mut p = goal;
+            while let Some(previous) = came_from[index(p)] {
+                if previous == start {
+                    break;
+                }
+                path.push(previous);
+                p = previous;
+            }
+            path.reverse();
+            return Some(path);
+        }
+        let next_cost = cost[index(current)] + 1;
+        for dir in Direction::ALL {
+            let next = board.step(current, dir);
+            if !board.in_bounds(next) || blocked(next) || next_cost >= cost[index(next)] {
+                continue;
+            }
+            cost[index(next)] = next_cost;
+            came_from[index(next)] = Some(current);
+            let estimate = board.distance(next, goal);
+            open.push(Reverse((next_cost + estimate, estimate, next.x, next.y)));
+        }
+    }
+    None
+}
+
+// Number of cells reachable from `start` without entering a blocked cell, `start` included.
+// Counting stops at `limit`, which is all a bot needs to know whether it fits.
+pub fn reachable_area(board: &Board, start: Point, blocked: impl Fn(Point) -> bool, limit: usize) -> usize {
+    if !board.in_bounds(start) {
+        return 0;
+    }
+    let width = board.width();
+    let index = |p: Point| (p.y * width + p.x) as usize;
+    let mut seen = vec![false; (width * board.height()) as usize];
+    let mut queue = VecDeque::from([start]);
+    seen[index(start)] = true;
+    let mut area = 0;
+    while let Some(current) = queue.pop_front() {
+        area += 1;
+        if area >= limit {
+            break;
+        }
+        for dir in Direction::ALL {
+            let next = board.step(current, dir);
+            if board.in_bounds(next) && !seen[index(next)] && !blocked(next) {
+                seen[index(next)] = true;
+                queue.push_back(next);
+            }
+        }
+    }
+    area
+}
+
+#[derive(Debug)]
+pub struct NoCycle {
+    pub width: i32,
+    pub height: i32,
+}
+
+impl fmt::Display for NoCycle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a {}x{} board has no Hamiltonian cycle, one side must be even", self.width, self.height)
+    }
+}
+
+impl std::error::Error for NoCycle {}
+
+// A closed path through every cell of the board, or through all but the block and a spare cell
+// once it has been routed around the block.
+// A snake that only moves forward along it, and never laps its own tail, can fill the whole board.
+#[derive(Clone, Debug)]
+pub struct HamiltonianCycle {
+    width: i32,
+    height: i32,
+    // The cells in the order the cycle visits them
+    path: Vec<Point>,
+    // Position of every cell along the cycle, indexed by y * width + x, None for cells left out.
+    // The spare shares the position of the cell it can stand in for.
+    order: Vec<Option<usize>>,
+}
+
+// Sides of the windows tried when routing around the block, and the dead ends the search may
+// run into in each before it gives up on it
+const WINDOW_SIZES: [i32; 3] = [4, 5, 6];
+const SEARCH_BUDGET: usize = 20_000;
+
+// How the route search leaves a cell of its window
+#[derive(Clone, Copy)]
+enum Exit {
+    // To a neighbour inside the window
+    Inside,
+    // Along the old cycle, forwards or backwards, until it comes back into the window
+    Outside(bool),
+}
+
+impl HamiltonianCycle {
+    // Snakes along the rows and comes back up the first column, which needs an even number of rows.
+    // With an odd number of rows but an even number of columns the same path is used on its side.
+    // When both sides are odd a cycle cannot exist, as it would alternate between two colours of a
+    // checkerboard with one colour more than the other.
+    pub fn new(width: i32, height: i32) -> Result<HamiltonianCycle, NoCycle> {
+        let transposed = match (width % 2 == 0, height % 2 == 0) {
+            (_, true) if width >= 2 => false,
+            (true, false) if height >= 2 => true,
+            _ => return Err(NoCycle { width, height }),
+        };
+        let (columns, rows) = if transposed { (height, width) } else { (width, height) };
+
+        let mut path = vec![];
+        for x in 0..columns {
+            path.push(Point { x, y: 0 });
+        }
+        for y in 1..rows {
+            if y % 2 == 1 {
+                for x in (1..columns).rev() {
+                    path.push(Point { x, y });
+                }
+            } else {
+                for x in 1..columns {
+                    path.push(Point { x, y });
+                }
+            }
+        }
+        for y in (1..rows).rev() {
+            path.push(Point { x: 0, y });
+        }
+        if transposed {
+            path = path.into_iter().map(|p| Point { x: p.y, y: p.x }).collect();
+        }
+        Ok(Self::from_path(width, height, path))
+    }
+
+    fn from_path(width: i32, height: i32, path: Vec<Point>) -> HamiltonianCycle {
+        let mut order = vec![None; (width * height) as usize];
+        for (position, p) in path.iter().enumerate() {
+            order[(p.y * width + p.x) as usize] = Some(position);
+        }
+        HamiltonianCycle { width, height, path, order }
+    }
+
+    // The cycle rerouted to leave out `block`. Leaving out a single cell cannot close a cycle, the
+    // two colours of the checkerboard would no longer match up, so a spare cell of the other colour
+    // is left out as well. The new route runs past the spare on both sides of a cell it shares a
+    // 2x2 square with, so a snake can take either of the two and food on the spare is not out of
+    // reach. Only a small window around the block is rerouted, `None` when no window works out.
+    pub fn around(&self, block: Point) -> Option<HamiltonianCycle> {
+        for side in WINDOW_SIZES {
+            let (w, h) = (side.min(self.width), side.min(self.height));
+            for y0 in (block.y + 1 - h).max(0)..=block.y.min(self.height - h) {
+                for x0 in (block.x + 1 - w).max(0)..=block.x.min(self.width - w) {
+                    let window = (Point { x: x0, y: y0 }, Point { x: x0 + w, y: y0 + h });
+                    if let Some(route) = self.reroute(block, window) {
+                        return Some(route);
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    fn in_window(p: Point, (from, to): (Point, Point)) -> bool {
+        p.x >= from.x && p.x < to.x && p.y >= from.y && p.y < to.y
+    }
+
+    // Tries every spare in the window, closest to the block first
+    fn reroute(&self, block: Point, window: (Point, Point)) -> Option<HamiltonianCycle> {
+        let outside = |p: Point| !Self::in_window(p, window);
+        let leaves = |p: Point| self.cycle_neighbours(p).into_iter().filter(|n| outside(*n)).count();
+        if leaves(block) > 0 {
+            return None;
+        }
+        let mut spares: Vec<Point> = (window.0.y..window.1.y)
+            .flat_map(|y| (window.0.x..window.1.x).map(move |x| Point { x, y }))
+            .filter(|p| (p.x + p.y + block.x + block.y) % 2 == 1 && leaves(*p) == 0)
+            .collect();
+        spares.sort_by_key(|p| (p.x - block.x).abs() + (p.y - block.y).abs());
+        spares.into_iter().find_map(|spare| RouteSearch::new(self, window, block, spare).run())
+    }
+
+    // The cells before and after `p` on the cycle
+    fn cycle_neighbours(&self, p: Point) -> [Point; 2] {
+        let position = self.position(p).expect("only cells on the cycle are asked for");
+        [self.path[(position + self.len() - 1) % self.len()], self.path[(position + 1) % self.len()]]
+    }
+
+    fn len(&self) -> usize {
+        self.path.len()
+    }
+
+    // False for the block and for a spare the route had to leave out entirely
+    pub fn covers(&self, p: Point) -> bool {
+        self.position(p).is_some()
+    }
+
+    // Whether the body lies on the stretch of the cycle behind the head, in order, as it does for
+    // a snake that only ever moves forward along this cycle
+    pub fn holds(&self, snake: &Body) -> bool {
+        let Some(head) = self.position(snake[0]) else {
+            return false;
+        };
+        let mut behind = 0;
+        for p in snake.iter().skip(1) {
+            match self.position(*p).map(|position| self.distance(position, head)) {
+                Some(distance) if distance > behind => behind = distance,
+                _ => return false,
+            }
+        }
+        true
+    }
+
+    fn position(&self, p: Point) -> Option<usize> {
+        if p.x < 0 || p.x >= self.width || p.y < 0 || p.y >= self.height {
+            return None;
+        }
+        self.order[(p.y * self.width + p.x) as usize]
+    }
+
+    // How many steps along the cycle it takes to get from `from` to `to`
+    fn distance(&self, from: usize, to: usize) -> usize {
+        (to + self.len() - from) % self.len()
+    }
+
+    // Next move along the cycle, skipping ahead towards the food when that cannot trap the snake.
+    // Neither the cycle nor the shortcuts cross an edge, so this holds on a wrapping board as well.
+    // The body always sits on the stretch of the cycle behind the head, so any cell ahead of the
+    // head and before the tail is free. A shortcut may land anywhere in that stretch as long as it
+    // leaves slack for the snake to grow into, and never skips past the food. Cells for which
+    // `avoid` returns true are never entered, the snake's own body included, and when the cycle
+    // offers no way on the snake steps to any free cell instead.
+    pub fn next_move(&self, snake: &Body, food: Point, avoid: impl Fn(Point) -> bool) -> Option<Direction> {
+        let head = snake[0];
+        let neighbours: Vec<(Direction, Point)> = Direction::ALL
+            .into_iter()
+            .map(|dir| (dir, head.step(dir)))
+            .filter(|(_, p)| p.x >= 0 && p.x < self.width && p.y >= 0 && p.y < self.height && !avoid(*p))
+            .collect();
+        let any_free = neighbours.first().map(|(dir, _)| *dir);
+        let Some(head_position) = self.position(head) else {
+            return any_free;
+        };
+        // The last part of the body still on the cycle, the head counts when it is all there is
+        let tail = snake.iter().rev().find_map(|p| self.position(*p)).unwrap_or(head_position);
+        let to_tail = match self.distance(head_position, tail) {
+            0 => self.len(),
+            d => d,
+        };
+        let to_food = self.position(food).map_or(self.len(), |food| self.distance(head_position, food));
+        let empty = self.len().saturating_sub(snake.len() + 1);
+
+        // The tail cell itself cannot be entered, one more cell goes to growing and a few to spare
+        let mut max_skip = to_tail as i64 - 5;
+        if empty < self.len() / 2 {
+            // Too full to risk any shortcut
+            max_skip = 0;
+        } else if to_food < to_tail {
+            max_skip -= 1;
+            // The food sits far from the tail, so eating it leaves little slack for a while
+            if (to_tail - to_food) * 4 > empty {
+                max_skip -= 10;
+            }
+        }
+        // Food on the spare is only reached through the cell before it, a shortcut landing on the
+        // cell the spare stands in for would pass it by
+        let on_spare = self.position(food).is_some_and(|position| self.path[position] != food);
+        let mut max_skip = max_skip.min(to_food as i64 - i64::from(on_spare));
+
+        let steps: Vec<(Direction, Point, usize)> = neighbours
+            .iter()
+            .filter_map(|&(dir, p)| Some((dir, p, self.distance(head_position, self.position(p)?))))
+            .collect();
+        // The next cell of the cycle, or the spare standing in for it, whichever holds the food
+        let along_cycle = steps
+            .iter()
+            .filter(|(_, _, skip)| *skip == 1)
+            .max_by_key(|(_, p, _)| *p == food)
+            .map(|(dir, _, _)| *dir);
+        // Something lies straight ahead, so jump over it whenever there is any slack at all
+        if along_cycle.is_none() {
+            max_skip = max_skip.max((to_tail as i64 - 3).min(to_food as i64));
+        }
+
+        let mut best: Option<(usize, Direction)> = None;
+        for &(dir, p, skip) in &steps {
+            // A shortcut as far as the food has to land on it, not on a cell sharing its position
+            if skip <= 1 || skip as i64 > max_skip || (skip == to_food && p != food) {
+                continue;
+            }
+            if best.is_none_or(|(best_skip, _)| skip > best_skip) {
+                best = Some((skip, dir));
+            }
+        }
+        best.map(|(_, dir)| dir).or(along_cycle).or(any_free)
+    }
+}
+
+// Backtracking search for a new route through a window of the cycle, leaving out the block and
+// a spare. Outside the window the cycle stays as it was: the search walks the new route and takes
+// the old one whenever it leaves the window, until it closes back where it started.
+struct RouteSearch<'a> {
+    cycle: &'a HamiltonianCycle,
+    window: (Point, Point),
+    block: Point,
+    spare: Point,
+    visited: Vec<bool>,
+    // Cells still to visit inside the window
+    left: usize,
+    // The window cells in the order the new route visits them, with how it leaves each one
+    route: Vec<(Point, Exit)>,
+    budget: usize,
+}
+
+impl<'a> RouteSearch<'a> {
+    fn new(cycle: &'a HamiltonianCycle, window: (Point, Point), block: Point, spare: Point) -> RouteSearch<'a> {
+        let cells = ((window.1.x - window.0.x) * (window.1.y - window.0.y)) as usize;
+        RouteSearch {
+            cycle,
+            window,
+            block,
+            spare,
+            visited: vec![false; (cycle.width * cycle.height) as usize],
+            left: cells - 2,
+            route: vec![],
+            budget: SEARCH_BUDGET,
+        }
+    }
+
+    fn index(&self, p: Point) -> usize {
+        (p.y * self.cycle.width + p.x) as usize
+    }
+
+    fn inside(&self, p: Point) -> bool {
+        HamiltonianCycle::in_window(p, self.window) && p != self.block && p != self.spare
+    }
+
+    // Neighbours on the old cycle that lie outside the window, as the direction to walk towards them
+    fn exits(&self, p: Point) -> Vec<bool> {
+        let [before, after] = self.cycle.cycle_neighbours(p);
+        let outside = |n: Point| !HamiltonianCycle::in_window(n, self.window);
+        [(before, false), (after, true)].into_iter().filter(|(n, _)| outside(*n)).map(|(_, forwards)| forwards).collect()
+    }
+
+    // Where a walk along the old cycle from window cell `p` comes back into the window, and the
+    // direction it arrives from
+    fn come_back(&self, p: Point, forwards: bool) -> (Point, bool) {
+        let len = self.cycle.len();
+        let mut position = self.cycle.position(p).expect("window cells are on the cycle");
+        loop {
+            position = if forwards { (position + 1) % len } else { (position + len - 1) % len };
+            let cell = self.cycle.path[position];
+            if HamiltonianCycle::in_window(cell, self.window) {
+                // Arriving forwards means the cell was entered from its predecessor
+                return (cell, !forwards);
+            }
+        }
+    }
+
+    fn run(mut self) -> Option<HamiltonianCycle> {
+        let cells: Vec<Point> = (self.window.0.y..self.window.1.y)
+            .flat_map(|y| (self.window.0.x..self.window.1.x).map(move |x| Point { x, y }))
+            .filter(|p| self.inside(*p))
+            .collect();
+        // Start on a cell the old cycle enters the window through, as if it had just come in
+        let (start, arrived) = match cells.iter().find_map(|p| self.exits(*p).first().map(|forwards| (*p, *forwards))) {
+            Some((start, from_after)) => (start, Some(from_after)),
+            // The window is the whole board
+            None => (cells[0], None),
+        };
+        if self.walk(start, start, arrived) {
+            Some(self.build())
+        } else {
+            None
+        }
+    }
+
+    // Visits `cell`, arrived at from outside the window (`Some(true)` from the cell after it on the
+    // old cycle, `Some(false)` from the one before) or from inside (`None`), then walks on
+    fn walk(&mut self, cell: Point, start: Point, arrived: Option<bool>) -> bool {
+        if self.budget == 0 {
+            return false;
+        }
+        self.budget -= 1;
+        let index = self.index(cell);
+        self.visited[index] = true;
+        self.left -= 1;
+        // A way out of the window the cell did not come in by has to be taken, the old cycle runs
+        // on from there
+        let exit = self.exits(cell).into_iter().find(|forwards| arrived != Some(*forwards));
+        let found = match exit {
+            Some(forwards) => {
+                self.route.push((cell, Exit::Outside(forwards)));
+                let (next, from_after) = self.come_back(cell, forwards);
+                if next == start {
+                    self.left == 0 && self.spare_fits()
+                } else {
+                    !self.visited[self.index(next)] && self.walk(next, start, Some(from_after))
+                }
+            },
+            None => self.step_inside(cell, start),
+        };
+        if !found {
+            self.route.pop();
+            self.visited[index] = false;
+            self.left += 1;
+        }
+        found
+    }
+
+    fn step_inside(&mut self, cell: Point, start: Point) -> bool {
+        self.route.push((cell, Exit::Inside));
+        for dir in Direction::ALL {
+            let next = cell.step(dir);
+            if !self.inside(next) {
+                continue;
+            }
+            // Only a window covering the whole board closes from inside
+            if next == start && self.exits(start).is_empty() {
+                if self.left == 0 && self.spare_fits() {
+                    return true;
+                }
+                continue;
+            }
+            if self.visited[self.index(next)] || self.exits(next).len() == 2 {
+                continue;
+            }
+            if self.walk(next, start, None) {
+                return true;
+            }
+        }
+        false
+    }
+
+    // A spare boxed in by the block and the edges of the board is a dead end no route could pass
+    // through, so it is simply left out. Any other spare needs a cell to stand in for.
+    fn spare_fits(&self) -> bool {
+        let open = Direction::ALL
+            .into_iter()
+            .map(|dir| self.spare.step(dir))
+            .filter(|p| p.x >= 0 && p.x < self.cycle.width && p.y >= 0 && p.y < self.cycle.height && *p != self.block)
+            .count();
+        open <= 1 || self.stand_in().is_some()
+    }
+
+    // The route has to turn around the spare at one of its diagonal neighbours, so that the spare
+    // can be visited instead of that cell, or the spare could never be reached
+    fn stand_in(&self) -> Option<usize> {
+        let route = self.path();
+        let len = route.len();
+        let touches_spare = |p: Point| (p.x - self.spare.x).abs() + (p.y - self.spare.y).abs() == 1;
+        [(-1, -1), (1, -1), (-1, 1), (1, 1)]
+            .into_iter()
+            .filter_map(|(dx, dy)| route.position(Point { x: self.spare.x + dx, y: self.spare.y + dy }))
+            .find(|&position| touches_spare(route.path[(position + len - 1) % len]) && touches_spare(route.path[(position + 1) % len]))
+    }
+
+    // The whole new route, with the spare sharing the position of the cell it stands in for
+    fn build(&self) -> HamiltonianCycle {
+        let mut route = self.path();
+        let spare = (self.spare.y * self.cycle.width + self.spare.x) as usize;
+        route.order[spare] = self.stand_in();
+        route
+    }
+
+    fn path(&self) -> HamiltonianCycle {
+        let mut path = vec![];
+        for &(cell, exit) in &self.route {
+            path.push(cell);
+            if let Exit::Outside(forwards) = exit {
+                let len = self.cycle.len();
+                let mut position = self.cycle.position(cell).expect("window cells are on the cycle");
+                loop {
+                    position = if forwards { (position + 1) % len } else { (position + len - 1) % len };
+                    let next = self.cycle.path[position];
+                    if HamiltonianCycle::in_window(next, self.window) {
+                        break;
+                    }
+                    path.push(next);
+                }
+            }
+        }
+        HamiltonianCycle::from_path(self.cycle.width, self.cycle.height, path)
+    }
+}
+
+// Most free cells left for the endgame search to take over
+pub const ENDGAME_CELLS: usize = 4;
+
+// States the endgame search may look at for one meal before it settles for the best line so far
+const ENDGAME_BUDGET: usize = 50_000;
+
+// The best odds of filling the board and the moves up to the next meal that give them, once no
+// more than `ENDGAME_CELLS` cells but the block are left free. A full board is a Hamiltonian path
+// through every cell but the block, and as the snake never enters its own tail the path has to end
+// on the cell of the last food, next to the head. Food lands at random, so the search tries every
+// way the snake can move and eat and averages over where the next food may land, down to the last
+// cell. `None` while there are still too many cells left.
+pub fn endgame(board: &Board, snake: &Body, food: Point, block: Point) -> Option<(f64, Vec<Direction>)> {
+    if board.free_count().saturating_sub(usize::from(!board.is_occupied(block))) > ENDGAME_CELLS {
+        return None;
+    }
+    let free: Vec<Point> = (0..board.height())
+        .flat_map(|y| (0..board.width()).map(move |x| Point { x, y }))
+        .filter(|p| *p != block && !board.is_occupied(*p))
+        .collect();
+    if !free.contains(&food) {
+        return None;
+    }
+    Some(Endgame { board, budget: ENDGAME_BUDGET }.best(snake.clone(), free, food))
+}
+
+struct Endgame<'a> {
+    board: &'a Board,
+    budget: usize,
+}
+
+impl Endgame<'_> {
+    // Odds of filling the board with the snake on `snake`, `free` cells left and food on `food`,
+    // and the moves up to the meal that gives them. Breadth first over the moves that do not eat,
+    // every way of eating is followed by the average over where the food lands next.
+    fn best(&mut self, snake: Body, free: Vec<Point>, food: Point) -> (f64, Vec<Direction>) {
+        // Every state seen, with the state it was reached from and the move that got there
+        let mut steps: Vec<(Option<usize>, Direction)> = vec![];
+        let mut seen = HashSet::from([snake.clone()]);
+        let mut queue = VecDeque::from([(snake, free, None)]);
+        let mut best: (f64, Vec<Direction>) = (0.0, vec![]);
+        while let Some((snake, free, step)) = queue.pop_front() {
+            if self.budget == 0 {
+                break;
+            }
+            self.budget -= 1;
+            for dir in Direction::ALL {
+                let next = self.board.step(snake[0], dir);
+                if !free.contains(&next) {
+                    continue;
+                }
+                let mut body = snake.clone();
+                body.push_front(next);
+                if next == food {
+                    let rest: Vec<Point> = free.iter().copied().filter(|p| *p != food).collect();
+                    let odds = if rest.is_empty() {
+                        1.0
+                    } else {
+                        rest.iter().map(|p| self.best(body.clone(), rest.clone(), *p).0).sum::<f64>() / rest.len() as f64
+                    };
+                    if odds > best.0 {
+                        best = (odds, Self::moves(&steps, step, dir));
+                    }
+                    if odds >= 1.0 {
+                        return best;
+                    }
+                    continue;
+                }
+                let tail = body.pop_back().expect("the snake has a body");
+                if seen.insert(body.clone()) {
+                    let free = free.iter().copied().filter(|p| *p != next).chain([tail]).collect();
+                    steps.push((step, dir));
+                    queue.push_back((body, free, Some(steps.len() - 1)));
+                }
+            }
+        }
+        best
+    }
+
+    // The moves that lead to state `step`, followed by `last`
+    fn moves(steps: &[(Option<usize>, Direction)], mut step: Option<usize>, last: Direction) -> Vec<Direction> {
+        let mut moves = vec![last];
+        while let Some(index) = step {
+            moves.push(steps[index].1);
+            step = steps[index].0;
+        }
+        moves.reverse();
+        moves
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::Seat;
+
+    fn p(x: i32, y: i32) -> Point {
+        Point { x, y }
+    }
+
+    fn cells(width: i32, height: i32) -> impl Iterator<Item = Point> {
+        (0..height).flat_map(move |y| (0..width).map(move |x| p(x, y)))
+    }
+
+    fn touching(a: Point, b: Point) -> bool {
+        (a.x - b.x).abs() + (a.y - b.y).abs() == 1
+    }
+
+    #[test]
+    fn astar_walks_around_a_wall() {
+        let board = Board::new(5, 5, false);
+        let wall = |c: Point| c.x == 2 && c.y < 4;
+        let path = astar(&board, p(0, 0), p(4, 0), wall).unwrap();
+        assert_eq!(path.len(), 12);
+        assert_eq!(path.last(), Some(&p(4, 0)));
+        assert!(touching(p(0, 0), path[0]));
+        assert!(path.windows(2).all(|pair| touching(pair[0], pair[1])));
+        assert!(!path.iter().any(|c| wall(*c)));
+
+        assert_eq!(astar(&board, p(0, 0), p(0, 0), wall), Some(vec![]));
+        assert_eq!(astar(&board, p(0, 0), p(4, 0), |c: Point| c.x == 2), None);
+        // Across the edge of a wrapping board
+        assert_eq!(astar(&Board::new(5, 5, true), p(0, 0), p(4, 0), wall), Some(vec![p(4, 0)]));
+    }
+
+    #[test]
+    fn reachable_area_counts_the_pocket_up_to_the_limit() {
+        let board = Board::new(5, 5, false);
+        let wall = |c: Point| c.x == 2;
+        assert_eq!(reachable_area(&board, p(0, 0), wall, 100), 10);
+        assert_eq!(reachable_area(&board, p(4, 4), wall, 100), 10);
+        assert_eq!(reachable_area(&board, p(0, 0), wall, 4), 4);
+        assert_eq!(reachable_area(&board, p(0, 0), |_| false, 100), 25);
+        assert_eq!(reachable_area(&board, p(5, 0), wall, 100), 0);
+    }
+
+    #[test]
+    fn routes_around_the_block_close_over_every_other_cell() {
+        for (width, height) in [(4, 4), (6, 6), (5, 6), (10, 10), (12, 9)] {
+            let cycle = HamiltonianCycle::new(width, height).unwrap();
+            for block in cells(width, height) {
+                let route = cycle.around(block).unwrap_or_else(|| panic!("no route around {:?} on {}x{}", block, width, height));
+                let len = route.len();
+                assert_eq!(len, (width * height) as usize - 2);
+                for (position, cell) in route.path.iter().enumerate() {
+                    assert!(touching(*cell, route.path[(position + 1) % len]), "{:?} on {}x{}", block, width, height);
+                    assert_eq!(route.position(*cell), Some(position));
+                }
+                let spares: Vec<Point> = cells(width, height).filter(|c| *c != block && !route.path.contains(c)).collect();
+                let [spare] = spares[..] else { panic!("spares {:?} around {:?}", spares, block) };
+                match route.position(spare) {
+                    // Reached from both cells next to the one it stands in for
+                    Some(position) => {
+                        let [before, after] = route.cycle_neighbours(route.path[position]);
+                        assert!(touching(spare, before) && touching(spare, after));
+                    },
+                    // A dead end only the block leads away from
+                    None => assert_eq!(cells(width, height).filter(|c| *c != block && touching(*c, spare)).count(), 1),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn no_route_around_a_block_that_splits_the_board() {
+        let cycle = HamiltonianCycle::new(2, 6).unwrap();
+        assert!(cycle.around(p(0, 0)).is_some());
+        assert!(cycle.around(p(0, 2)).is_none());
+        assert!(HamiltonianCycle::new(5, 5).is_err());
+    }
+
+    #[test]
+    fn the_endgame_ends_on_the_last_free_cell() {
+        let mut board = Board::new(3, 2, false);
+        let block = p(2, 1);
+        let fill = |board: &mut Board, snake: &Body| {
+            for c in snake {
+                board.occupy(*c, Seat::PLAYER);
+            }
+        };
+        let snake: Body = [p(0, 1), p(0, 0), p(1, 0), p(2, 0)].into();
+        fill(&mut board, &snake);
+        assert_eq!(endgame(&board, &snake, p(1, 1), block), Some((1.0, vec![Direction::Right])));
+
+        let mut board = Board::new(3, 2, false);
+        let snake: Body = [p(2, 0), p(1, 0), p(0, 0), p(0, 1)].into();
+        fill(&mut board, &snake);
+        assert_eq!(endgame(&board, &snake, p(1, 1), block), Some((0.0, vec![])));
+        assert_eq!(endgame(&Board::new(4, 4, false), &[p(0, 0)].into(), p(1, 1), block), None);
+    }
+}
@@ -0,0 +1,217 @@
+// Command line interface for Rusty Snake.
+// Without a command the game opens on the menu, every other command jumps straight into a mode.
+
+use clap::{Args, Parser, Subcommand};
+use rusty_snake::level::Level;
+use rusty_snake::game::MAX_SNAKES;
+use rusty_snake::net::DEFAULT_PORT;
+use rusty_snake::strategy::StrategyKind;
+use rusty_snake::settings::{FoodTie, HeadOn, Settings};
+use std::path::PathBuf;
+
+#[derive(Parser)]
+#[command(name = "rusty_snake", version, about = "A simple snake game programmed at rust")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    #[command(flatten)]
+    pub options: GameOptions,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Open the menu (default)
+    Menu,
+    /// Steer the snake with the arrow keys
+    Play {
+        /// Strategy steering the snake: human, greedy, pathfinder, hamiltonian or exec:<command>
+        #[arg(long, default_value = "human")]
+        player: StrategyKind,
+    },
+    /// Watch the bot play on its own
+    Selfplay {
+        /// Strategy steering the snake: greedy, pathfinder, hamiltonian or exec:<command>
+        #[arg(long, default_value = "pathfinder")]
+        bot: StrategyKind,
+    },
+    /// Race the bot for the food
+    Versus {
+        /// Strategy steering the white snake
+        #[arg(long, default_value = "human")]
+        player: StrategyKind,
+        /// Strategy steering the yellow snake, `human` for a second player on WASD or a gamepad
+        #[arg(long, default_value = "greedy")]
+        bot: StrategyKind,
+    },
+    /// Free-for-all between several snakes, the last one left wins
+    Arena {
+        /// Strategies of the snakes, 2 to 8 of them. Only the first two can be human: the arrow
+        /// keys steer the first and WASD the second.
+        #[arg(required = true, num_args = 2..=MAX_SNAKES)]
+        snakes: Vec<StrategyKind>,
+    },
+    /// Play many games without a window and report how the strategies did
+    Simulate {
+        /// Number of games, played with --seed, --seed + 1 and so on
+        #[arg(long, default_value_t = 1000)]
+        games: usize,
+        /// Strategy steering the first snake
+        #[arg(long, default_value = "pathfinder")]
+        player: StrategyKind,
+        /// Strategy steering a second snake, the games are single player without one
+        #[arg(long)]
+        bot: Option<StrategyKind>,
+        /// Stop a game after this many ticks
+        #[arg(long, default_value_t = 100_000)]
+        max_ticks: u64,
+        /// Games played at once, one per CPU by default
+        #[arg(long)]
+        threads: Option<usize>,
+    },
+    /// Rank competitive strategies against each other
+    Tournament {
+        /// Strategies taking part, at least two
+        #[arg(required = true, num_args = 2..)]
+        strategies: Vec<StrategyKind>,
+        /// Seeds per pairing, each one is played from both sides of the board
+        #[arg(long, default_value_t = 10)]
+        games: usize,
+        /// Play this many Swiss rounds instead of a round robin
+        #[arg(long, value_name = "ROUNDS")]
+        swiss: Option<usize>,
+        /// Stop a game after this many ticks
+        #[arg(long, default_value_t = 100_000)]
+        max_ticks: u64,
+        /// Games played at once, one per CPU by default
+        #[arg(long)]
+        threads: Option<usize>,
+        /// Write the standings to a CSV file
+        #[arg(long)]
+        csv: Option<PathBuf>,
+        /// Write the standings to a JSON file
+        #[arg(long)]
+        json: Option<PathBuf>,
+    },
+    /// Host a game against a player on another machine, the host steers the white snake
+    Host {
+        /// Port to wait on for the other player
+        #[arg(long, default_value_t = DEFAULT_PORT)]
+        port: u16,
+        /// Strategy steering the white snake
+        #[arg(long, default_value = "human")]
+        player: StrategyKind,
+        /// Play without a window and print the result, for bots
+        #[arg(long)]
+        headless: bool,
+    },
+    /// Join a game hosted on another machine and steer the yellow snake
+    Join {
+        /// Address of the host, e.g. 192.168.1.20 or localhost:7878
+        address: String,
+        /// Strategy steering the yellow snake
+        #[arg(long, default_value = "human")]
+        player: StrategyKind,
+        /// Play without a window and print the result, for bots
+        #[arg(long)]
+        headless: bool,
+    },
+    /// Watch a game broadcast by another instance started with --broadcast
+    Spectate {
+        /// Address of the broadcasting machine, e.g. 192.168.1.20 or localhost:7879
+        address: String,
+    },
+    /// Watch a saved replay
+    Replay {
+        file: PathBuf,
+    },
+}
+
+// Options shared by every command, each setting flag overrides the value from the settings file
+#[derive(Args)]
+pub struct GameOptions {
+    /// Settings file to load instead of rusty_snake.toml
+    #[arg(long, global = true)]
+    pub config: Option<PathBuf>,
+
+    /// Board size as <width>x<height>, e.g. 30x20
+    #[arg(long, global = true, value_parser = parse_grid)]
+    pub grid: Option<(i32, i32)>,
+
+    /// Pixels per cell when the window opens
+    #[arg(long, global = true)]
+    pub cell_size: Option<f32>,
+
+    /// Seconds between two moves
+    #[arg(long, global = true, value_name = "SECONDS")]
+    pub speed: Option<f32>,
+
+    /// Seconds an external bot gets to answer each move
+    #[arg(long, global = true, value_name = "SECONDS")]
+    pub move_timeout: Option<f32>,
+
+    /// Let snakes leave the board at one edge and come back in on the opposite one
+    #[arg(long, global = true)]
+    pub wrap: bool,
+
+    /// What happens when snakes move their heads onto the same cell: longer-wins or both-die
+    #[arg(long, global = true, value_name = "RULE")]
+    pub head_on: Option<HeadOn>,
+
+    /// Who scores food reached together by snakes that all die doing it: nobody or shared
+    #[arg(long, global = true, value_name = "RULE")]
+    pub food_tie: Option<FoodTie>,
+
+    /// Level to play on, a bundled one, one in levels/ or a path to a level file
+    #[arg(long, global = true, value_name = "NAME")]
+    pub level: Option<String>,
+
+    /// Seed for food and block spawns, a random one is used otherwise
+    #[arg(long, global = true)]
+    pub seed: Option<u64>,
+
+    /// Let spectators watch the games played here, on this port
+    #[arg(long, global = true, value_name = "PORT")]
+    pub broadcast: Option<u16>,
+}
+
+impl GameOptions {
+    pub fn settings(&self) -> Result<Settings, String> {
+        let mut settings = Settings::load_or_default(self.config.as_deref()).map_err(|e| e.to_string())?;
+        if let Some((width, height)) = self.grid {
+            settings.grid_width = width;
+            settings.grid_height = height;
+        }
+        if let Some(cell_size) = self.cell_size {
+            settings.cell_size = cell_size;
+        }
+        if let Some(speed) = self.speed {
+            settings.move_interval = speed;
+        }
+        if let Some(move_timeout) = self.move_timeout {
+            settings.move_timeout = move_timeout;
+        }
+        if self.wrap {
+            settings.wrap = true;
+        }
+        if let Some(head_on) = self.head_on {
+            settings.head_on = head_on;
+        }
+        if let Some(food_tie) = self.food_tie {
+            settings.food_tie = food_tie;
+        }
+        settings.validate().map_err(|e| e.to_string())?;
+        Ok(settings)
+    }
+
+    pub fn level(&self) -> Result<Option<Level>, String> {
+        self.level.as_deref().map(Level::find).transpose().map_err(|e| e.to_string())
+    }
+}
+
+fn parse_grid(value: &str) -> Result<(i32, i32), String> {
+    value
+        .split_once('x')
+        .and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)))
+        .ok_or(format!("expected <width>x<height>, got {}", value))
+}
@@ -0,0 +1,221 @@
+// Level editor for the window frontend.
+// Walls and food spawners are painted with the mouse, the left button adds and the right one
+// removes. Starts are dropped where the mouse clicks and turned with the arrow keys.
+
+use ggez::event::{KeyCode, MouseButton};
+use rusty_snake::game::{Direction, Point};
+use rusty_snake::level::{Level, Start};
+use std::fs;
+use std::path::PathBuf;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Tool {
+    Wall,
+    Food,
+    Single,
+    Player,
+    Bot,
+}
+
+impl Tool {
+    // In the order of the number keys that pick them
+    pub const ALL: [Tool; 5] = [Tool::Wall, Tool::Food, Tool::Single, Tool::Player, Tool::Bot];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Tool::Wall => "walls",
+            Tool::Food => "food spawners",
+            Tool::Single => "single start",
+            Tool::Player => "player start",
+            Tool::Bot => "bot start",
+        }
+    }
+}
+
+// What the editor needs the rest of the frontend for after a key press
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Action {
+    None,
+    // Pick a level to edit from the level list
+    Load,
+    // Play the level as it is, against the greedy bot when true
+    Test { versus: bool },
+    Quit,
+}
+
+pub struct Editor {
+    pub level: Level,
+    pub tool: Tool,
+    // Set while a mouse button is held, true when it paints and false when it erases
+    painting: Option<bool>,
+    // Typed characters go to the level name while this is set
+    pub naming: bool,
+    // The N that starts naming arrives as a typed character as well, and is dropped
+    skip_char: bool,
+    pub message: Option<String>,
+}
+
+impl Editor {
+    // Edits a copy of `level`, the open board becomes a new level
+    pub fn new(level: &Level) -> Editor {
+        let mut level = level.clone();
+        if level.is_open() {
+            level.name = "custom".to_string();
+            level.title = "custom".to_string();
+        }
+        Editor { level, tool: Tool::Wall, painting: None, naming: false, skip_char: false, message: None }
+    }
+
+    fn start_mut(&mut self, tool: Tool) -> Option<&mut Start> {
+        match tool {
+            Tool::Single => Some(&mut self.level.single),
+            Tool::Player => Some(&mut self.level.player),
+            Tool::Bot => Some(&mut self.level.bot),
+            Tool::Wall | Tool::Food => None,
+        }
+    }
+
+    pub fn press(&mut self, cell: Point, button: MouseButton) {
+        let paint = match button {
+            MouseButton::Left => true,
+            MouseButton::Right => false,
+            _ => return,
+        };
+        self.painting = Some(paint);
+        self.apply(cell, paint);
+    }
+
+    pub fn drag(&mut self, cell: Point) {
+        if let Some(paint) = self.painting {
+            self.apply(cell, paint);
+        }
+    }
+
+    pub fn release(&mut self) {
+        self.painting = None;
+    }
+
+    fn apply(&mut self, cell: Point, paint: bool) {
+        if cell.x < 0 || cell.x >= self.level.width || cell.y < 0 || cell.y >= self.level.height {
+            return;
+        }
+        match self.tool {
+            Tool::Wall => {
+                let on_start = [self.level.single, self.level.player, self.level.bot].iter().any(|start| start.at == cell);
+                if !(paint && on_start) {
+                    self.level.set_wall(cell, paint);
+                }
+            },
+            Tool::Food => self.level.set_food(cell, paint),
+            tool => {
+                if paint && !self.level.walls.contains(&cell) {
+                    if let Some(start) = self.start_mut(tool) {
+                        start.at = cell;
+                    }
+                }
+            },
+        }
+    }
+
+    pub fn key(&mut self, keycode: KeyCode) -> Action {
+        if self.naming {
+            match keycode {
+                KeyCode::Back => {
+                    self.level.name.pop();
+                    self.level.title = self.level.name.clone();
+                },
+                KeyCode::Return | KeyCode::Escape => self.naming = false,
+                _ => {},
+            }
+            return Action::None;
+        }
+        self.message = None;
+        let (width, height) = (self.level.width, self.level.height);
+        match keycode {
+            KeyCode::Key1 | KeyCode::Key2 | KeyCode::Key3 | KeyCode::Key4 | KeyCode::Key5 => {
+                let keys = [KeyCode::Key1, KeyCode::Key2, KeyCode::Key3, KeyCode::Key4, KeyCode::Key5];
+                let index = keys.iter().position(|key| *key == keycode).expect("one of the number keys");
+                self.tool = Tool::ALL[index];
+            },
+            KeyCode::Up | KeyCode::Down | KeyCode::Left | KeyCode::Right => {
+                let heading = match keycode {
+                    KeyCode::Up => Direction::Up,
+                    KeyCode::Down => Direction::Down,
+                    KeyCode::Left => Direction::Left,
+                    _ => Direction::Right,
+                };
+                match self.start_mut(self.tool) {
+                    Some(start) => start.heading = heading,
+                    None => self.message = Some("Pick a start with 3, 4 or 5 to turn it".to_string()),
+                }
+            },
+            KeyCode::LBracket => self.level.resize(width - 1, height),
+            KeyCode::RBracket => self.level.resize(width + 1, height),
+            KeyCode::Minus => self.level.resize(width, height - 1),
+            KeyCode::Equals => self.level.resize(width, height + 1),
+            KeyCode::N => {
+                self.naming = true;
+                self.skip_char = true;
+                self.level.name.clear();
+                self.level.title.clear();
+            },
+            KeyCode::S => {
+                self.message = Some(match self.save() {
+                    Ok(path) => format!("Saved to {}", path.display()),
+                    Err(e) => format!("Not saved: {}", e),
+                });
+            },
+            KeyCode::L => return Action::Load,
+            KeyCode::Return => return Action::Test { versus: false },
+            KeyCode::V => return Action::Test { versus: true },
+            KeyCode::Escape => return Action::Quit,
+            _ => {},
+        }
+        Action::None
+    }
+
+    // Letters, digits, dashes and underscores make up a level name, so it is always a valid file name
+    pub fn type_char(&mut self, c: char) {
+        if std::mem::take(&mut self.skip_char) {
+            return;
+        }
+        if self.naming && (c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+            self.level.name.push(c);
+            self.level.title = self.level.name.clone();
+        }
+    }
+
+    fn save(&self) -> Result<PathBuf, String> {
+        let name = &self.level.name;
+        if name.is_empty() {
+            return Err("give the level a name with N first".to_string());
+        }
+        if Level::is_bundled(name) {
+            return Err(format!("{} is a bundled level, give it another name with N", name));
+        }
+        self.level.validate()?;
+        let path = Level::path(name);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        }
+        self.level.save(&path).map_err(|e| e.to_string())?;
+        Ok(path)
+    }
+
+    // Lines shown above the board
+    pub fn status(&self) -> String {
+        let naming = if self.naming { "   Type a name, Enter when done" } else { "" };
+        let mut status = format!(
+            "Editing {} ({}x{})   Tool: {}{}\n1 walls  2 food  3-5 starts  arrows turn a start  [ ] width  - = height\nN rename  S save  L load  Enter play  V play versus the bot  Esc menu",
+            self.level.name,
+            self.level.width,
+            self.level.height,
+            self.tool.name(),
+            naming,
+        );
+        if let Some(message) = &self.message {
+            status.push_str(&format!("\n{}", message));
+        }
+        status
+    }
+}
@@ -0,0 +1,440 @@
+// Gym-style environment for training agents on the single player rules.
+// An episode is one game: `reset` starts it from a seed, `step` plays one move and reports the
+// reward, whether the game is done, and what happened during the tick.
+
+use rayon::prelude::*;
+use std::sync::Arc;
+use crate::bot;
+use crate::game::{Death, Direction, Game, Mode, Moves, PERFECT_GAME_BONUS, Point, Seat};
+use crate::level::Level;
+use crate::settings::Settings;
+
+// Reward for each event of a tick, summed when several happen at once
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Rewards {
+    pub food: f32,
+    pub block: f32,
+    pub death: f32,
+    // Paid every tick the snake survives, a small negative value pushes agents to hurry
+    pub step: f32,
+    // Paid once when the snake fills the board
+    pub perfect: f32,
+}
+
+impl Default for Rewards {
+    // Mirrors the score: +10 for food, -5 for the block and the bonus for a perfect game
+    fn default() -> Rewards {
+        Rewards { food: 10.0, block: -5.0, death: -10.0, step: 0.0, perfect: PERFECT_GAME_BONUS as f32 }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Encoding {
+    // Channels for head, body, food, block and walls, each height x width, channel first
+    #[default]
+    Grid,
+    // A short summary of the head's surroundings, see FEATURES
+    Features,
+}
+
+// Length of the feature vector, walls count as danger:
+// danger up, down, left, right; heading one-hot; food dx, dy; block dx, dy; length; room up, down, left, right
+pub const FEATURES: usize = 4 + 4 + 2 + 2 + 1 + 4;
+pub const GRID_CHANNELS: usize = 5;
+
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct EnvConfig {
+    pub settings: Settings,
+    // Played on an open board of the size in `settings` when None
+    pub level: Option<Arc<Level>>,
+    pub rewards: Rewards,
+    pub encoding: Encoding,
+    // Episodes still running after this many steps end as truncated
+    pub max_steps: Option<u64>,
+}
+
+// What happened during a step, besides the reward
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Info {
+    pub score: i32,
+    pub length: usize,
+    pub steps: u64,
+    pub ate_food: bool,
+    pub hit_block: bool,
+    pub death: Option<Death>,
+    // The snake filled the board, which ends the episode
+    pub perfect: bool,
+    // The episode hit max_steps with the snake still alive
+    pub truncated: bool,
+}
+
+pub type Observation = Vec<f32>;
+
+#[derive(Clone, Debug)]
+pub struct SnakeEnv {
+    config: EnvConfig,
+    game: Game,
+    steps: u64,
+    done: bool,
+}
+
+impl SnakeEnv {
+    pub fn new(config: EnvConfig) -> SnakeEnv {
+        SnakeEnv {
+            game: new_game(&config, 0),
+            config,
+            steps: 0,
+            done: false,
+        }
+    }
+
+    pub fn config(&self) -> &EnvConfig {
+        &self.config
+    }
+
+    pub fn game(&self) -> &Game {
+        &self.game
+    }
+
+    // Number of actions, actions are indices into Direction::ALL
+    pub fn action_count(&self) -> usize {
+        Direction::ALL.len()
+    }
+
+    pub fn observation_len(&self) -> usize {
+        match self.config.encoding {
+            Encoding::Grid => GRID_CHANNELS * (self.game.width() * self.game.height()) as usize,
+            Encoding::Features => FEATURES,
+        }
+    }
+
+    pub fn reset(&mut self, seed: u64) -> Observation {
+        self.game = new_game(&self.config, seed);
+        self.steps = 0;
+        self.done = false;
+        self.observation()
+    }
+
+    // Plays one move. Once the episode is done every further step does nothing until the next reset.
+    pub fn step(&mut self, action: Direction) -> (Observation, f32, bool, Info) {
+        let (reward, info) = self.advance(action);
+        (self.observation(), reward, self.done, info)
+    }
+
+    // Like `step` without building an observation, for callers that fill their own buffers
+    pub fn advance(&mut self, action: Direction) -> (f32, Info) {
+        if self.done {
+            return (0.0, self.info());
+        }
+        let (food, block) = (self.game.food(), self.game.block());
+        self.game.advance(Moves(vec![Some(action)]));
+        self.steps += 1;
+
+        let rewards = self.config.rewards;
+        let mut info = self.info();
+        let mut reward = rewards.step;
+        if let Some(&head) = self.game.snake().front().filter(|_| info.death.is_none()) {
+            info.ate_food = head == food;
+            info.hit_block = head == block;
+        }
+        if info.ate_food {
+            reward += rewards.food;
+        }
+        if info.hit_block {
+            reward += rewards.block;
+        }
+        if info.death.is_some() {
+            reward += rewards.death;
+            self.done = true;
+        } else if info.perfect {
+            reward += rewards.perfect;
+            self.done = true;
+        } else if self.config.max_steps.is_some_and(|max| self.steps >= max) {
+            info.truncated = true;
+            self.done = true;
+        }
+        (reward, info)
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    fn info(&self) -> Info {
+        Info {
+            score: self.game.score(),
+            length: self.game.snake().len(),
+            steps: self.steps,
+            death: self.game.death(Seat::PLAYER),
+            perfect: self.game.is_perfect(),
+            ..Info::default()
+        }
+    }
+
+    pub fn observation(&self) -> Observation {
+        let mut observation = vec![0.0; self.observation_len()];
+        self.observe_into(&mut observation);
+        observation
+    }
+
+    // Writes the observation into `out`, which must be observation_len() long
+    pub fn observe_into(&self, out: &mut [f32]) {
+        out.fill(0.0);
+        match self.config.encoding {
+            Encoding::Grid => self.grid_into(out),
+            Encoding::Features => self.features_into(out),
+        }
+    }
+
+    fn grid_into(&self, out: &mut [f32]) {
+        let game = &self.game;
+        let cells = (game.width() * game.height()) as usize;
+        let index = |channel: usize, p: Point| channel * cells + (p.y * game.width() + p.x) as usize;
+        for (i, &segment) in game.snake().iter().enumerate() {
+            if game.board().in_bounds(segment) {
+                out[index(if i == 0 { 0 } else { 1 }, segment)] = 1.0;
+            }
+        }
+        out[index(2, game.food())] = 1.0;
+        out[index(3, game.block())] = 1.0;
+        for &wall in game.board().walls() {
+            out[index(4, wall)] = 1.0;
+        }
+    }
+
+    fn features_into(&self, out: &mut [f32]) {
+        let game = &self.game;
+        let snake = game.snake();
+        let head = snake[0];
+        let (width, height) = (game.width(), game.height());
+        let board = game.board();
+        let blocked = |p: Point| !board.in_bounds(p) || board.is_occupied(p);
+        let cells = (width * height) as f32;
+
+        for (i, dir) in Direction::ALL.into_iter().enumerate() {
+            let next = board.step(head, dir);
+            out[i] = if blocked(next) { 1.0 } else { 0.0 };
+            // Cells reachable after the move, as a share of twice the snake's length
+            let room = if blocked(next) { 0 } else { bot::reachable_area(board, next, blocked, snake.len() * 2) };
+            out[13 + i] = room as f32 / (snake.len() * 2) as f32;
+        }
+        let heading = game.view(Seat::PLAYER, None).heading;
+        let i = Direction::ALL.iter().position(|dir| *dir == heading).expect("every direction is listed");
+        out[4 + i] = 1.0;
+        // Offsets take the short way around on a wrapping board
+        let (food, block) = (board.offset(head, game.food()), board.offset(head, game.block()));
+        out[8] = food.x as f32 / width as f32;
+        out[9] = food.y as f32 / height as f32;
+        out[10] = block.x as f32 / width as f32;
+        out[11] = block.y as f32 / height as f32;
+        out[12] = snake.len() as f32 / cells;
+    }
+}
+
+fn new_game(config: &EnvConfig, seed: u64) -> Game {
+    match &config.level {
+        Some(level) => Game::on_level(Mode::Single, seed, &config.settings, level.clone()),
+        None => Game::new(Mode::Single, seed, &config.settings),
+    }
+}
+
+// Many independent environments stepped together over rayon's thread pool.
+// Results land in flat buffers with one row per environment, ready to hand to a training library.
+// A finished episode is reset straight away, so its row already holds the first observation of
+// the next episode while `dones` and `infos` still describe the one that just ended.
+pub struct VecEnv {
+    envs: Vec<SnakeEnv>,
+    // Environment i plays its k-th episode with seed + i + k * len, whatever order the threads run in
+    seed: u64,
+    episodes: Vec<u64>,
+    observations: Vec<f32>,
+    rewards: Vec<f32>,
+    dones: Vec<bool>,
+    infos: Vec<Info>,
+}
+
+// Borrowed views of a VecEnv's buffers after a step
+pub struct VecStep<'a> {
+    // len() rows of observation_len() values
+    pub observations: &'a [f32],
+    pub rewards: &'a [f32],
+    pub dones: &'a [bool],
+    pub infos: &'a [Info],
+}
+
+impl VecEnv {
+    pub fn new(config: EnvConfig, count: usize, seed: u64) -> VecEnv {
+        let envs = vec![SnakeEnv::new(config); count];
+        let observation_len = envs.first().map_or(0, |env| env.observation_len());
+        let mut vec_env = VecEnv {
+            envs,
+            seed,
+            episodes: vec![0; count],
+            observations: vec![0.0; count * observation_len],
+            rewards: vec![0.0; count],
+            dones: vec![false; count],
+            infos: vec![Info::default(); count],
+        };
+        vec_env.reset();
+        vec_env
+    }
+
+    pub fn len(&self) -> usize {
+        self.envs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.envs.is_empty()
+    }
+
+    pub fn observation_len(&self) -> usize {
+        self.envs.first().map_or(0, |env| env.observation_len())
+    }
+
+    pub fn envs(&self) -> &[SnakeEnv] {
+        &self.envs
+    }
+
+    // Restarts every environment on its first seed
+    pub fn reset(&mut self) -> &[f32] {
+        let seed = self.seed;
+        let observation_len = self.observation_len().max(1);
+        self.episodes.fill(0);
+        self.rewards.fill(0.0);
+        self.dones.fill(false);
+        self.infos.fill(Info::default());
+        self.envs
+            .par_iter_mut()
+            .zip(self.observations.par_chunks_mut(observation_len))
+            .enumerate()
+            .for_each(|(i, (env, out))| {
+                env.reset(seed.wrapping_add(i as u64));
+                env.observe_into(out);
+            });
+        &self.observations
+    }
+
+    // Plays one action in every environment, `actions` holds one per environment
+    pub fn step(&mut self, actions: &[Direction]) -> VecStep<'_> {
+        assert_eq!(actions.len(), self.envs.len(), "one action per environment");
+        let (seed, count) = (self.seed, self.envs.len() as u64);
+        let observation_len = self.observation_len().max(1);
+        self.envs
+            .par_iter_mut()
+            .zip(self.observations.par_chunks_mut(observation_len))
+            .zip(self.rewards.par_iter_mut())
+            .zip(self.dones.par_iter_mut())
+            .zip(self.infos.par_iter_mut())
+            .zip(self.episodes.par_iter_mut())
+            .enumerate()
+            .for_each(|(i, (((((env, out), reward), done), info), episode))| {
+                (*reward, *info) = env.advance(actions[i]);
+                *done = env.is_done();
+                if *done {
+                    *episode += 1;
+                    env.reset(seed.wrapping_add(i as u64).wrapping_add(*episode * count));
+                }
+                env.observe_into(out);
+            });
+        VecStep { observations: &self.observations, rewards: &self.rewards, dones: &self.dones, infos: &self.infos }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> EnvConfig {
+        let settings = Settings { grid_width: 10, grid_height: 10, ..Settings::default() };
+        let rewards = Rewards { step: -0.01, ..Rewards::default() };
+        EnvConfig { settings, rewards, encoding: Encoding::Features, ..EnvConfig::default() }
+    }
+
+    // First step of the shortest way to the food
+    fn to_food(env: &SnakeEnv) -> Direction {
+        let game = env.game();
+        let head = game.snake()[0];
+        let path = bot::astar(game.board(), head, game.food(), |p| p == game.block() || game.board().is_occupied(p)).unwrap();
+        game.board().direction_to(head, path[0]).unwrap()
+    }
+
+    #[test]
+    fn reset_starts_the_same_episode_for_the_same_seed() {
+        let mut env = SnakeEnv::new(config());
+        let first = env.reset(3);
+        assert_eq!(first.len(), FEATURES);
+        env.step(Direction::Up);
+        assert_eq!(env.reset(3), first);
+        assert!(!env.is_done());
+        assert_eq!(env.game().ticks(), 0);
+
+        let mut grid = SnakeEnv::new(EnvConfig { encoding: Encoding::Grid, ..config() });
+        assert_eq!(grid.reset(3).len(), GRID_CHANNELS * 100);
+    }
+
+    #[test]
+    fn steps_pay_for_food_and_death() {
+        let mut env = SnakeEnv::new(config());
+        env.reset(1);
+        let (_, reward, done, info) = loop {
+            let step = env.step(to_food(&env));
+            if step.3.ate_food {
+                break step;
+            }
+            assert_eq!(step.1, -0.01);
+        };
+        assert_eq!(reward, 10.0 - 0.01);
+        assert!(!done);
+        assert_eq!(info.score, 10);
+
+        let (_, reward, done, info) = loop {
+            let step = env.step(Direction::Up);
+            if step.2 {
+                break step;
+            }
+        };
+        assert_eq!(reward, -10.0 - 0.01);
+        assert_eq!(info.death, Some(Death::Wall));
+        assert!(done);
+        // Nothing happens until the next reset
+        let (_, reward, done, info) = env.step(Direction::Down);
+        assert_eq!((reward, done, info.steps), (0.0, true, env.info().steps));
+    }
+
+    #[test]
+    fn steps_end_as_truncated_at_max_steps() {
+        let mut env = SnakeEnv::new(EnvConfig { max_steps: Some(3), ..config() });
+        env.reset(1);
+        let dones: Vec<bool> = [Direction::Up, Direction::Left, Direction::Down].into_iter().map(|dir| env.step(dir).2).collect();
+        assert_eq!(dones, vec![false, false, true]);
+        assert!(env.info().death.is_none());
+    }
+
+    #[test]
+    fn a_vec_env_plays_like_its_envs_one_by_one() {
+        let config = EnvConfig { max_steps: Some(15), ..config() };
+        let (count, seed) = (4, 7);
+        let mut vec_env = VecEnv::new(config.clone(), count, seed);
+        let mut envs: Vec<SnakeEnv> = (0..count).map(|_| SnakeEnv::new(config.clone())).collect();
+        let mut episodes = vec![0; count];
+        for (i, env) in envs.iter_mut().enumerate() {
+            env.reset(seed + i as u64);
+        }
+        for tick in 0..50 {
+            let actions: Vec<Direction> = (0..count).map(|i| Direction::ALL[(i + tick / 4) % 4]).collect();
+            let step = vec_env.step(&actions);
+            for (i, env) in envs.iter_mut().enumerate() {
+                let (observation, reward, done, info) = env.step(actions[i]);
+                assert_eq!((step.rewards[i], step.dones[i], step.infos[i]), (reward, done, info), "env {} tick {}", i, tick);
+                let observation = if done {
+                    episodes[i] += 1;
+                    env.reset(seed + i as u64 + episodes[i] * count as u64)
+                } else {
+                    observation
+                };
+                assert_eq!(&step.observations[i * FEATURES..(i + 1) * FEATURES], observation.as_slice());
+            }
+        }
+        assert!(episodes.iter().all(|episodes| *episodes > 0));
+    }
+}
@@ -0,0 +1,158 @@
+// Bots that run as a separate program and talk to the game over stdin and stdout.
+//
+// Every tick the game writes the board as one line of JSON:
+//
+//     {"turn":3,"width":50,"height":40,"wrap":false,"walls":[[0,0],[1,0]],"food":[12,3],"block":[4,5],
+//      "you":{"body":[[10,3],[9,3]],"heading":"right","score":10},
+//      "opponents":[{"body":[[30,20]],"score":0}]}
+//
+// Points are [x, y] with y growing downwards. With `wrap` set a snake leaving one edge of the
+// board comes back in on the opposite one. The program answers with one line holding
+// `up`, `down`, `left` or `right`, either bare or as {"move":"up"}.
+// A late answer keeps the snake going the way it was heading, a bad one or a closed pipe
+// counts as giving up.
+
+use serde::Serialize;
+use std::io::{self, BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+use crate::game::{Body, Direction, View};
+use crate::strategy::Strategy;
+
+#[derive(Serialize)]
+struct Board {
+    turn: u64,
+    width: i32,
+    height: i32,
+    wrap: bool,
+    walls: Vec<[i32; 2]>,
+    food: [i32; 2],
+    block: [i32; 2],
+    you: You,
+    opponents: Vec<Snake>,
+}
+
+#[derive(Serialize)]
+struct You {
+    body: Vec<[i32; 2]>,
+    heading: &'static str,
+    score: i32,
+}
+
+#[derive(Serialize)]
+struct Snake {
+    body: Vec<[i32; 2]>,
+    score: i32,
+}
+
+#[derive(serde::Deserialize)]
+struct Reply {
+    #[serde(rename = "move")]
+    dir: String,
+}
+
+pub struct External {
+    command: String,
+    child: Child,
+    stdin: ChildStdin,
+    // Lines read from the child's stdout by a background thread
+    lines: Receiver<String>,
+    timeout: Duration,
+    turn: u64,
+}
+
+impl External {
+    // Starts `command`, split on whitespace into the program and its arguments
+    pub fn spawn(command: &str, timeout: Duration) -> io::Result<External> {
+        let mut parts = command.split_whitespace();
+        let program = parts.next().ok_or(io::Error::new(io::ErrorKind::InvalidInput, "empty command"))?;
+        let mut child = Command::new(program)
+            .args(parts)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let stdin = child.stdin.take().expect("stdin is piped");
+        let stdout = child.stdout.take().expect("stdout is piped");
+
+        let (sender, lines) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(External { command: command.to_string(), child, stdin, lines, timeout, turn: 0 })
+    }
+
+    fn board(&self, view: &View) -> Board {
+        let body = |snake: &Body| snake.iter().map(|p| [p.x, p.y]).collect();
+        Board {
+            turn: self.turn,
+            width: view.width,
+            height: view.height,
+            wrap: view.board.wraps(),
+            walls: view.board.walls().iter().map(|p| [p.x, p.y]).collect(),
+            food: [view.food.x, view.food.y],
+            block: [view.block.x, view.block.y],
+            you: You { body: body(view.snake), heading: view.heading.name(), score: view.score },
+            opponents: view.opponents.iter().map(|o| Snake { body: body(o.snake), score: o.score }).collect(),
+        }
+    }
+}
+
+impl Strategy for External {
+    fn name(&self) -> String {
+        self.command.clone()
+    }
+
+    fn choose(&mut self, view: &View) -> Option<Direction> {
+        // Answers that came in after their timeout belong to earlier turns
+        while self.lines.try_recv().is_ok() {}
+
+        let board = serde_json::to_string(&self.board(view)).expect("board always serializes");
+        self.turn += 1;
+        if writeln!(self.stdin, "{}", board).and_then(|_| self.stdin.flush()).is_err() {
+            eprintln!("rusty_snake: {} closed its input", self.command);
+            return None;
+        }
+        match self.lines.recv_timeout(self.timeout) {
+            Ok(line) => {
+                let dir = parse_move(&line);
+                if dir.is_none() {
+                    eprintln!("rusty_snake: {} answered {:?}, expected a move", self.command, line);
+                }
+                dir
+            },
+            Err(RecvTimeoutError::Timeout) => {
+                eprintln!("rusty_snake: {} did not answer within {:.2}s", self.command, self.timeout.as_secs_f32());
+                Some(view.heading)
+            },
+            Err(RecvTimeoutError::Disconnected) => {
+                eprintln!("rusty_snake: {} exited", self.command);
+                None
+            },
+        }
+    }
+}
+
+impl Drop for External {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+fn parse_move(line: &str) -> Option<Direction> {
+    let line = line.trim();
+    let word = match serde_json::from_str::<Reply>(line) {
+        Ok(reply) => reply.dir,
+        Err(_) => line.trim_matches('"').to_string(),
+    };
+    Direction::ALL.into_iter().find(|dir| dir.name().eq_ignore_ascii_case(&word))
+}
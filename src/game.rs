@@ -0,0 +1,1144 @@
+// Game rules for Rusty Snake.
+// Nothing in here knows about ggez, so the rules can run without a window.
+
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use std::cmp;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::Arc;
+use crate::board::Board;
+use crate::level::Level;
+use crate::settings::{FoodTie, HeadOn, Settings};
+
+// A snake's body, head first
+pub type Body = VecDeque<Point>;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub struct Point {
+    pub x: i32,
+    pub y: i32,
+}
+
+impl Point {
+    pub fn step(self, dir: Direction) -> Point {
+        let delta = dir.delta();
+        Point { x: self.x + delta.x, y: self.y + delta.y }
+    }
+
+    // Direction that leads from this point to a neighbouring one
+    pub fn direction_to(self, next: Point) -> Option<Direction> {
+        Direction::ALL.into_iter().find(|dir| self.step(*dir) == next)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl Direction {
+    pub const ALL: [Direction; 4] = [Direction::Up, Direction::Down, Direction::Left, Direction::Right];
+
+    pub fn delta(self) -> Point {
+        match self {
+            Direction::Up => Point { x: 0, y: -1 },
+            Direction::Down => Point { x: 0, y: 1 },
+            Direction::Left => Point { x: -1, y: 0 },
+            Direction::Right => Point { x: 1, y: 0 },
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Direction::Up => "up",
+            Direction::Down => "down",
+            Direction::Left => "left",
+            Direction::Right => "right",
+        }
+    }
+
+    pub fn opposite(self) -> Direction {
+        match self {
+            Direction::Up => Direction::Down,
+            Direction::Down => Direction::Up,
+            Direction::Left => Direction::Right,
+            Direction::Right => Direction::Left,
+        }
+    }
+}
+
+// Which rules a game is played with
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Mode {
+    // One snake on its own, steered by a human or a bot
+    Single,
+    // The player snake races the bot snake for the food
+    Competitive,
+}
+
+// The snakes of a game, numbered from 0. In single mode the one snake sits in the player seat,
+// a competitive game seats the player and the bot first and any further snakes after them.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub struct Seat(pub usize);
+
+impl Seat {
+    pub const PLAYER: Seat = Seat(0);
+    pub const BOT: Seat = Seat(1);
+}
+
+// Most snakes a game can hold
+pub const MAX_SNAKES: usize = 8;
+
+// Turns requested from the keyboard for a single tick, the arrow keys steer the player seat and
+// WASD the bot seat
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Inputs {
+    pub player: Option<Direction>,
+    pub bot: Option<Direction>,
+}
+
+impl Inputs {
+    pub fn get(&self, seat: Seat) -> Option<Direction> {
+        match seat {
+            Seat::PLAYER => self.player,
+            Seat::BOT => self.bot,
+            _ => None,
+        }
+    }
+}
+
+// The direction every snake actually moved in during a tick, by seat.
+// In single mode the one snake uses the player seat, `None` means the snake had no move left.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct Moves(pub Vec<Option<Direction>>);
+
+impl Moves {
+    // Moves of the player and the bot seat
+    pub fn pair(player: Option<Direction>, bot: Option<Direction>) -> Moves {
+        Moves(vec![player, bot])
+    }
+
+    pub fn get(&self, seat: Seat) -> Option<Direction> {
+        self.0.get(seat.0).copied().flatten()
+    }
+
+    pub fn set(&mut self, seat: Seat, dir: Option<Direction>) {
+        if self.0.len() <= seat.0 {
+            self.0.resize(seat.0 + 1, None);
+        }
+        self.0[seat.0] = dir;
+    }
+}
+
+// Why a snake's game ended
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub enum Death {
+    // Ran off the board or into a wall of the level
+    Wall,
+    // Ran into its own body
+    Itself,
+    // Ran into another snake
+    Opponent,
+    // Its strategy had no move left to make
+    Trapped,
+    // Lost a head-on collision with another snake
+    HeadOn,
+}
+
+impl Death {
+    pub const ALL: [Death; 5] = [Death::Wall, Death::Itself, Death::Opponent, Death::Trapped, Death::HeadOn];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Death::Wall => "wall",
+            Death::Itself => "self",
+            Death::Opponent => "opponent",
+            Death::Trapped => "trapped",
+            Death::HeadOn => "head-on",
+        }
+    }
+}
+
+// One snake of a game and how it has done so far
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Snake {
+    // Head first, kept as it was when the snake died
+    pub body: Body,
+    // Where the snake last moved
+    pub heading: Direction,
+    pub score: i32,
+    pub death: Option<Death>,
+    // Tick the snake died on, the later the better it places
+    pub died_at: Option<u64>,
+}
+
+impl Snake {
+    fn new(at: Point, heading: Direction) -> Snake {
+        Snake { body: Body::from([at]), heading, score: 0, death: None, died_at: None }
+    }
+
+    pub fn is_alive(&self) -> bool {
+        self.death.is_none()
+    }
+}
+
+// Which rule settled a cell that several snakes moved onto in the same tick
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Rule {
+    // The longest snake survived the head-on
+    LongerWins,
+    // Longer wins, but the longest snakes were equally long and all died
+    EqualLength,
+    // Every snake in the head-on died
+    BothDie,
+    // The snakes that died reaching the food together left it to nobody
+    FoodUnclaimed,
+    // The snakes that died reaching the food together all scored it
+    FoodShared,
+}
+
+impl Rule {
+    pub fn describe(self) -> &'static str {
+        match self {
+            Rule::LongerWins => "the longer snake wins a head-on",
+            Rule::EqualLength => "equally long snakes all lose a head-on",
+            Rule::BothDie => "head-ons kill every snake in them",
+            Rule::FoodUnclaimed => "food reached together goes to nobody",
+            Rule::FoodShared => "food reached together is shared",
+        }
+    }
+}
+
+// A rule deciding the fate of snakes that met on a cell
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Ruling {
+    pub tick: u64,
+    // Where the heads met, for snakes that swapped cells the one the first of them moved into
+    pub cell: Point,
+    // Every snake whose head moved onto the cell
+    pub seats: Vec<Seat>,
+    pub rule: Rule,
+    // The snake left on the cell, if any
+    pub winner: Option<Seat>,
+}
+
+// Food or block placed during a tick
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Spawn {
+    Food(Point),
+    Block(Point),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum StepOutcome {
+    Running,
+    GameOver,
+}
+
+// What a strategy gets to see of the board when it is asked for a move
+pub struct View<'a> {
+    pub seat: Seat,
+    pub width: i32,
+    pub height: i32,
+    // The snake being steered, head first, and where it last moved
+    pub snake: &'a Body,
+    pub heading: Direction,
+    pub score: i32,
+    // Every other snake still alive, empty in single mode
+    pub opponents: Vec<Opponent<'a>>,
+    pub food: Point,
+    pub block: Point,
+    // Which snake covers each cell
+    pub board: &'a Board,
+    // Turn requested from the keyboard, only human players look at it
+    pub input: Option<Direction>,
+}
+
+pub struct Opponent<'a> {
+    pub seat: Seat,
+    pub snake: &'a Body,
+    pub score: i32,
+}
+
+impl View<'_> {
+    pub fn in_bounds(&self, p: Point) -> bool {
+        self.board.in_bounds(p)
+    }
+
+    // The cell a move from `p` leads to, which wraps around the edges on a wrapping board
+    pub fn step(&self, p: Point, dir: Direction) -> Point {
+        self.board.step(p, dir)
+    }
+
+    // Number of moves between two cells on an empty board
+    pub fn distance(&self, a: Point, b: Point) -> i32 {
+        self.board.distance(a, b)
+    }
+
+    // True when another snake covers the cell
+    pub fn is_opponent(&self, p: Point) -> bool {
+        self.board.occupant(p).is_some_and(|seat| seat != self.seat)
+    }
+
+    pub fn is_wall(&self, p: Point) -> bool {
+        self.board.is_wall(p)
+    }
+
+    // True when any snake, this one included, or a wall covers the cell
+    pub fn is_occupied(&self, p: Point) -> bool {
+        self.board.is_occupied(p)
+    }
+}
+
+// Random cells tried before a spawn falls back to the free cell set.
+// Food in a single player game skips the block's cell, so a replay recorded before that rule
+// spawns elsewhere once a draw lands on the block, and playback reports the desync.
+const SPAWN_ATTEMPTS: usize = 64;
+
+// FNV-1a over little endian words, unlike the std hashers it is the same everywhere
+struct StateHash(u64);
+
+impl Default for StateHash {
+    fn default() -> StateHash {
+        StateHash(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl StateHash {
+    fn add(&mut self, value: u64) {
+        for byte in value.to_le_bytes() {
+            self.0 = (self.0 ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn add_point(&mut self, p: Point) {
+        self.add(p.x as u64);
+        self.add(p.y as u64);
+    }
+}
+
+// Body returned for a seat that is not taken
+static NO_SNAKE: Body = Body::new();
+
+// Added to the score of a single player game that ends with the snake filling the board
+pub const PERFECT_GAME_BONUS: i32 = 1000;
+
+const FOOD_POINTS: i32 = 10;
+const BLOCK_PENALTY: i32 = 5;
+
+#[derive(Clone, Debug)]
+pub struct Game {
+    mode: Mode,
+    seed: u64,
+    width: i32,
+    height: i32,
+    wrap: bool,
+    head_on: HeadOn,
+    food_tie: FoodTie,
+    // Walls and starts, shared between the copies of a game
+    level: Arc<Level>,
+    // Every spawn draws from this, so a seed always replays the same board
+    rng: ChaCha8Rng,
+    // Cells covered by the snakes, kept in step with the bodies below
+    board: Board,
+
+    // By seat, a single game has one snake and a competitive one two or more
+    snakes: Vec<Snake>,
+
+    food: Point,
+    block: Point,
+    game_over: bool,
+    // The snakes cover every cell but the block's, so no food could be placed.
+    // In single mode that is a perfect game.
+    board_full: bool,
+    // Ticks played so far
+    ticks: u64,
+
+    last_moves: Moves,
+    last_spawns: Vec<Spawn>,
+    // Every contested cell so far, in the order they were settled
+    rulings: Vec<Ruling>,
+}
+
+impl Game {
+    // A game on an open board of the size in `settings`
+    pub fn new(mode: Mode, seed: u64, settings: &Settings) -> Game {
+        Self::on_level(mode, seed, settings, Arc::new(Level::open(settings.grid_width, settings.grid_height)))
+    }
+
+    // A game on `level`, whose size wins over the one in `settings`.
+    // A competitive game is played between the player and the bot.
+    pub fn on_level(mode: Mode, seed: u64, settings: &Settings, level: Arc<Level>) -> Game {
+        let seats = match mode {
+            Mode::Single => 1,
+            Mode::Competitive => 2,
+        };
+        Self::with_seats(mode, seed, settings, level, seats)
+    }
+
+    // A competitive game between `snakes` snakes, the last one left standing wins
+    pub fn arena(seed: u64, settings: &Settings, level: Arc<Level>, snakes: usize) -> Game {
+        assert!((2..=MAX_SNAKES).contains(&snakes), "an arena holds 2 to {} snakes", MAX_SNAKES);
+        Self::with_seats(Mode::Competitive, seed, settings, level, snakes)
+    }
+
+    fn with_seats(mode: Mode, seed: u64, settings: &Settings, level: Arc<Level>, seats: usize) -> Game {
+        let mut game = Game {
+            mode,
+            seed,
+            width: level.width,
+            height: level.height,
+            wrap: settings.wrap,
+            head_on: settings.head_on,
+            food_tie: settings.food_tie,
+            board: Board::new(level.width, level.height, settings.wrap),
+            level,
+            rng: ChaCha8Rng::seed_from_u64(seed),
+
+            snakes: vec![Snake::new(Point { x: 0, y: 0 }, Direction::Right); seats],
+
+            food: Point { x: 0, y: 0 },
+            block: Point { x: 0, y: 0 },
+            game_over: false,
+            board_full: false,
+            ticks: 0,
+
+            last_moves: Moves::default(),
+            last_spawns: vec![],
+            rulings: vec![],
+        };
+        game.reset();
+        game
+    }
+
+    pub fn reset(&mut self) {
+        self.game_over = false;
+        self.board_full = false;
+        self.ticks = 0;
+        self.last_moves = Moves::default();
+        self.last_spawns.clear();
+        self.rulings.clear();
+        self.rng = ChaCha8Rng::seed_from_u64(self.seed);
+        self.board = Board::new(self.width, self.height, self.wrap);
+        for &wall in &self.level.walls {
+            self.board.add_wall(wall);
+        }
+        let starts = self.level.starts(self.snakes.len());
+        self.snakes = starts.iter().map(|start| Snake::new(start.at, start.heading)).collect();
+        for (i, start) in starts.iter().enumerate() {
+            self.board.occupy(start.at, Seat(i));
+        }
+        self.food = self.spawn_food(None).expect("a new board has free cells");
+        self.block = self.spawn(Some(self.food)).unwrap_or(self.food);
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn width(&self) -> i32 {
+        self.width
+    }
+
+    pub fn height(&self) -> i32 {
+        self.height
+    }
+
+    // True when snakes leaving one edge come back in on the opposite one
+    pub fn wraps(&self) -> bool {
+        self.wrap
+    }
+
+    pub fn head_on(&self) -> HeadOn {
+        self.head_on
+    }
+
+    pub fn food_tie(&self) -> FoodTie {
+        self.food_tie
+    }
+
+    pub fn level(&self) -> &Arc<Level> {
+        &self.level
+    }
+
+    pub fn board(&self) -> &Board {
+        &self.board
+    }
+
+    // Every snake by seat
+    pub fn snakes(&self) -> &[Snake] {
+        &self.snakes
+    }
+
+    // Number of snakes the game started with
+    pub fn seats(&self) -> usize {
+        self.snakes.len()
+    }
+
+    // The snake of a single player game
+    pub fn snake(&self) -> &Body {
+        &self.snakes[0].body
+    }
+
+    pub fn score(&self) -> i32 {
+        self.snakes[0].score
+    }
+
+    pub fn player_snake(&self) -> &Body {
+        &self.snakes[Seat::PLAYER.0].body
+    }
+
+    // Empty in single mode
+    pub fn bot_snake(&self) -> &Body {
+        self.snakes.get(Seat::BOT.0).map_or(&NO_SNAKE, |snake| &snake.body)
+    }
+
+    pub fn player_score(&self) -> i32 {
+        self.snakes[Seat::PLAYER.0].score
+    }
+
+    pub fn bot_score(&self) -> i32 {
+        self.snakes.get(Seat::BOT.0).map_or(0, |snake| snake.score)
+    }
+
+    pub fn food(&self) -> Point {
+        self.food
+    }
+
+    pub fn block(&self) -> Point {
+        self.block
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.game_over
+    }
+
+    // True when the game ended because the snakes filled every cell
+    pub fn is_board_full(&self) -> bool {
+        self.board_full
+    }
+
+    // A single player game won by filling the whole board
+    pub fn is_perfect(&self) -> bool {
+        self.board_full && self.mode == Mode::Single
+    }
+
+    pub fn ticks(&self) -> u64 {
+        self.ticks
+    }
+
+    // Fingerprint of everything that decides how the game goes on. Two games that were started
+    // alike and fed the same moves hash the same, on any machine.
+    pub fn state_hash(&self) -> u64 {
+        let mut hash = StateHash::default();
+        for snake in &self.snakes {
+            hash.add(snake.body.len() as u64);
+            for p in &snake.body {
+                hash.add_point(*p);
+            }
+            hash.add(snake.heading as u64);
+            hash.add(snake.score as u64);
+            hash.add(snake.death.map_or(0, |death| death as u64 + 1));
+            hash.add(snake.died_at.map_or(0, |tick| tick + 1));
+        }
+        hash.add_point(self.food);
+        hash.add_point(self.block);
+        hash.add(u64::from(self.game_over));
+        hash.add(self.ticks);
+        hash.add(self.rng.get_word_pos() as u64);
+        hash.0
+    }
+
+    // How the snake in `seat` died, `None` while it is alive
+    pub fn death(&self, seat: Seat) -> Option<Death> {
+        self.snakes.get(seat.0).and_then(|snake| snake.death)
+    }
+
+    // Every seat with its place, best first. Snakes still alive place above the dead ones, which
+    // place in the reverse order they died in. Snakes that went out on the same tick are told
+    // apart by their score and share a place when that is equal too.
+    pub fn ranking(&self) -> Vec<(usize, Seat)> {
+        let key = |snake: &Snake| (cmp::Reverse(snake.died_at.unwrap_or(u64::MAX)), cmp::Reverse(snake.score));
+        let mut seats: Vec<usize> = (0..self.snakes.len()).collect();
+        seats.sort_by_key(|&i| key(&self.snakes[i]));
+        seats
+            .into_iter()
+            .map(|i| {
+                let ahead = self.snakes.iter().filter(|other| key(other) < key(&self.snakes[i])).count();
+                (ahead + 1, Seat(i))
+            })
+            .collect()
+    }
+
+    // How every contested cell of the game was settled, oldest first
+    pub fn rulings(&self) -> &[Ruling] {
+        &self.rulings
+    }
+
+    // Moves made during the last tick
+    pub fn last_moves(&self) -> &Moves {
+        &self.last_moves
+    }
+
+    // Food and blocks placed during the last tick
+    pub fn last_spawns(&self) -> &[Spawn] {
+        &self.last_spawns
+    }
+
+    // Everything the snake in `seat` may base its next move on
+    pub fn view(&self, seat: Seat, input: Option<Direction>) -> View<'_> {
+        let seat = if self.mode == Mode::Single { Seat::PLAYER } else { seat };
+        let own = &self.snakes[seat.0];
+        let opponents = self
+            .snakes
+            .iter()
+            .enumerate()
+            .filter(|(i, snake)| *i != seat.0 && snake.is_alive())
+            .map(|(i, snake)| Opponent { seat: Seat(i), snake: &snake.body, score: snake.score })
+            .collect();
+        View {
+            seat,
+            width: self.width,
+            height: self.height,
+            snake: &own.body,
+            heading: own.heading,
+            score: own.score,
+            opponents,
+            food: self.food,
+            block: self.block,
+            board: &self.board,
+            input,
+        }
+    }
+
+    // Advance the game by one tick, moving every snake in the given direction
+    pub fn advance(&mut self, moves: Moves) -> StepOutcome {
+        if !self.game_over {
+            let (food, block) = (self.food, self.block);
+            self.last_spawns.clear();
+            self.ticks += 1;
+            match self.mode {
+                Mode::Single => self.update_single(moves.get(Seat::PLAYER)),
+                Mode::Competitive => self.update_competitive(&moves),
+            }
+            // One move per seat, however many the caller gave
+            self.last_moves = Moves((0..self.snakes.len()).map(|i| moves.get(Seat(i))).collect());
+            if self.food != food {
+                self.last_spawns.push(Spawn::Food(self.food));
+            }
+            if self.block != block {
+                self.last_spawns.push(Spawn::Block(self.block));
+            }
+        }
+        if self.game_over {
+            StepOutcome::GameOver
+        } else {
+            StepOutcome::Running
+        }
+    }
+
+    // A free cell for food, or for the block when `food` is given so the two never share a cell.
+    // `None` means the snakes cover the whole board.
+    fn spawn(&mut self, food: Option<Point>) -> Option<Point> {
+        for _ in 0..SPAWN_ATTEMPTS {
+            let point = Point {
+                x: self.rng.gen_range(0..self.width),
+                y: self.rng.gen_range(0..self.height),
+            };
+            if !self.board.is_occupied(point) && Some(point) != food {
+                return Some(point);
+            }
+        }
+        self.board.random_free(&mut self.rng, food)
+    }
+
+    // A free food spawner of the level, or any free cell when the level has none or all are covered.
+    // Never `block`, when given.
+    fn spawn_food(&mut self, block: Option<Point>) -> Option<Point> {
+        let spawners: Vec<Point> = self.level.food.iter().copied().filter(|p| !self.board.is_occupied(*p) && Some(*p) != block).collect();
+        if spawners.is_empty() {
+            self.spawn(block)
+        } else {
+            Some(spawners[self.rng.gen_range(0..spawners.len())])
+        }
+    }
+
+    // Places new food after a snake ate, and in competitive mode a new block as well.
+    // Ends the game when the snakes leave no room for food. The block does not count as room, a
+    // snake could never cover its cell without being cut in half.
+    fn respawn_food(&mut self) {
+        let block_free = !self.board.is_occupied(self.block);
+        if self.board.free_count() == usize::from(block_free) {
+            self.board_full = true;
+            self.game_over = true;
+            return;
+        }
+        // The block only stays put in single mode, food placed on it could not be eaten without
+        // running into it
+        let block = (self.mode == Mode::Single).then_some(self.block);
+        self.food = self.spawn_food(block).expect("a cell besides the block is free");
+        if self.mode == Mode::Competitive {
+            self.respawn_block();
+        }
+    }
+
+    // The block stays put when there is no free cell left for it
+    fn respawn_block(&mut self) {
+        if let Some(block) = self.spawn(Some(self.food)) {
+            self.block = block;
+        }
+    }
+
+    // What the snake in `seat` would hit when its head moves to `p`
+    fn collision(&self, seat: Seat, p: Point) -> Option<Death> {
+        if !self.board.in_bounds(p) || self.board.is_wall(p) {
+            return Some(Death::Wall);
+        }
+        match self.board.occupant(p) {
+            Some(occupant) if occupant == seat => Some(Death::Itself),
+            Some(_) => Some(Death::Opponent),
+            None => None,
+        }
+    }
+
+    fn body_mut(&mut self, seat: Seat) -> &mut Body {
+        &mut self.snakes[seat.0].body
+    }
+
+    fn kill(&mut self, seat: Seat, death: Death) {
+        let snake = &mut self.snakes[seat.0];
+        snake.death = Some(death);
+        snake.died_at = Some(self.ticks);
+    }
+
+    // Moves the snake in `seat` onto `head`, which must be free, and returns the score change.
+    // The tail follows unless the snake ate, and the block cuts the snake in half.
+    fn move_snake(&mut self, seat: Seat, head: Point) -> i32 {
+        let mut points = 0;
+        self.body_mut(seat).push_front(head);
+        self.board.occupy(head, seat);
+        if head == self.food {
+            points += FOOD_POINTS;
+            self.respawn_food();
+            if self.board_full {
+                if self.mode == Mode::Single {
+                    points += PERFECT_GAME_BONUS;
+                }
+                return points;
+            }
+        } else if let Some(tail) = self.body_mut(seat).pop_back() {
+            self.board.vacate(tail);
+        }
+        if head == self.block {
+            points -= self.cut_in_half(seat);
+        }
+        points
+    }
+
+    // Halves the snake in `seat` after it ran into the block and returns the penalty
+    fn cut_in_half(&mut self, seat: Seat) -> i32 {
+        let penalty = self.halve(seat);
+        self.respawn_block();
+        penalty
+    }
+
+    // Drops the back half of the snake in `seat` and returns the penalty, leaving the block put
+    fn halve(&mut self, seat: Seat) -> i32 {
+        let new_length = cmp::max(1, self.body_mut(seat).len() / 2);
+        while self.body_mut(seat).len() > new_length {
+            if let Some(tail) = self.body_mut(seat).pop_back() {
+                self.board.vacate(tail);
+            }
+        }
+        BLOCK_PENALTY
+    }
+
+    fn update_single(&mut self, dir: Option<Direction>) {
+        if let Some(dir) = dir {
+            self.snakes[0].heading = dir;
+            let new_head = self.board.step(self.snakes[0].body[0], dir);
+            if let Some(death) = self.collision(Seat::PLAYER, new_head) {
+                self.kill(Seat::PLAYER, death);
+                self.game_over = true;
+                return;
+            }
+            let points = self.move_snake(Seat::PLAYER, new_head);
+            self.snakes[0].score = cmp::max(0, self.snakes[0].score + points);
+        } else {
+            self.kill(Seat::PLAYER, Death::Trapped);
+            self.game_over = true;
+        }
+    }
+
+    // Which snakes of a head-on die by the head-on rule, and the one that survives if any
+    fn settle_head_on(&self, seats: &[Seat]) -> (Rule, Option<Seat>) {
+        match self.head_on {
+            HeadOn::BothDie => (Rule::BothDie, None),
+            HeadOn::LongerWins => {
+                let longest = seats.iter().map(|seat| self.snakes[seat.0].body.len()).max().unwrap_or(0);
+                match seats.iter().filter(|seat| self.snakes[seat.0].body.len() == longest).collect::<Vec<_>>()[..] {
+                    [winner] => (Rule::LongerWins, Some(*winner)),
+                    _ => (Rule::EqualLength, None),
+                }
+            },
+        }
+    }
+
+    // Every snake moves at the same time, none of them sees where the others go first. Heads
+    // that run into a wall or a body die, where the tail of another snake moving out of the way
+    // this tick leaves room. A snake never enters its own tail, as in the single player rules.
+    // Heads that meet on a cell, or snakes that swap cells, are settled by the head-on rule, and food reached by snakes that
+    // all die there by the food tie rule. Each of those decisions is kept as a ruling. The bodies
+    // of dead snakes are cleared off the board and the game ends once at most one snake is left.
+    fn update_competitive(&mut self, moves: &Moves) {
+        let n = self.snakes.len();
+        let mut heads: Vec<Option<Point>> = vec![None; n];
+        for (i, head) in heads.iter_mut().enumerate() {
+            if !self.snakes[i].is_alive() {
+                continue;
+            }
+            match moves.get(Seat(i)) {
+                Some(dir) => {
+                    self.snakes[i].heading = dir;
+                    *head = Some(self.board.step(self.snakes[i].body[0], dir));
+                },
+                None => self.kill(Seat(i), Death::Trapped),
+            }
+        }
+        // A snake that is about to eat keeps its tail
+        let leaving: Vec<Option<Point>> =
+            (0..n).map(|i| heads[i].filter(|head| *head != self.food).and_then(|_| self.snakes[i].body.back().copied())).collect();
+
+        // Snakes moving onto each other's heads swap cells, which is a head-on as well. The
+        // survivor may take the cell of the snake it beat.
+        let mut beaten: Vec<Option<Point>> = vec![None; n];
+        for i in 0..n {
+            for j in i + 1..n {
+                let (Some(a), Some(b)) = (heads[i], heads[j]) else { continue };
+                if a != self.snakes[j].body[0] || b != self.snakes[i].body[0] {
+                    continue;
+                }
+                let seats = vec![Seat(i), Seat(j)];
+                let (rule, winner) = self.settle_head_on(&seats);
+                for &seat in &seats {
+                    if Some(seat) != winner {
+                        self.kill(seat, Death::HeadOn);
+                    }
+                }
+                match winner {
+                    Some(Seat(k)) if k == i => (beaten[i], heads[j]) = (Some(a), None),
+                    Some(_) => (beaten[j], heads[i]) = (Some(b), None),
+                    None => (heads[i], heads[j]) = (None, None),
+                }
+                self.rulings.push(Ruling { tick: self.ticks, cell: a, seats, rule, winner });
+            }
+        }
+
+        for (i, slot) in heads.iter_mut().enumerate() {
+            let Some(head) = *slot else { continue };
+            let death = match self.collision(Seat(i), head) {
+                Some(Death::Opponent) if beaten[i] == Some(head) => None,
+                Some(Death::Opponent) if (0..n).any(|j| j != i && leaving[j] == Some(head)) => None,
+                death => death,
+            };
+            if let Some(death) = death {
+                self.kill(Seat(i), death);
+                *slot = None;
+            }
+        }
+
+        let mut points = vec![0; n];
+        let mut food_taken = false;
+        for i in 0..n {
+            let Some(cell) = heads[i] else { continue };
+            let seats: Vec<Seat> = (i..n).filter(|&j| heads[j] == Some(cell)).map(Seat).collect();
+            if seats.len() < 2 {
+                continue;
+            }
+            let (rule, winner) = self.settle_head_on(&seats);
+            for &seat in &seats {
+                if Some(seat) != winner {
+                    self.kill(seat, Death::HeadOn);
+                    heads[seat.0] = None;
+                }
+            }
+            self.rulings.push(Ruling { tick: self.ticks, cell, seats: seats.clone(), rule, winner });
+            if winner.is_none() && cell == self.food {
+                let rule = match self.food_tie {
+                    FoodTie::Nobody => Rule::FoodUnclaimed,
+                    FoodTie::Shared => {
+                        for seat in &seats {
+                            points[seat.0] += FOOD_POINTS;
+                        }
+                        food_taken = true;
+                        Rule::FoodShared
+                    },
+                };
+                self.rulings.push(Ruling { tick: self.ticks, cell, seats, rule, winner });
+            }
+        }
+
+        // Tails first, so a head can take the cell a tail just left
+        for i in 0..n {
+            if let (Some(_), Some(tail)) = (heads[i], leaving[i]) {
+                self.snakes[i].body.pop_back();
+                if self.board.occupant(tail) == Some(Seat(i)) {
+                    self.board.vacate(tail);
+                }
+            }
+        }
+        for i in 0..n {
+            if self.snakes[i].died_at == Some(self.ticks) {
+                for p in self.snakes[i].body.clone() {
+                    if self.board.occupant(p) == Some(Seat(i)) {
+                        self.board.vacate(p);
+                    }
+                }
+            }
+        }
+        // Every head is on the board before the block moves, so it cannot land on one
+        for (i, head) in heads.iter().enumerate() {
+            let Some(head) = *head else { continue };
+            self.snakes[i].body.push_front(head);
+            self.board.occupy(head, Seat(i));
+        }
+        let mut block_hit = false;
+        for i in 0..n {
+            let Some(head) = heads[i] else { continue };
+            if head == self.food {
+                points[i] += FOOD_POINTS;
+                food_taken = true;
+            } else if head == self.block {
+                points[i] -= self.halve(Seat(i));
+                block_hit = true;
+            }
+        }
+        if block_hit {
+            self.respawn_block();
+        }
+        for (snake, points) in self.snakes.iter_mut().zip(points) {
+            snake.score = cmp::max(0, snake.score + points);
+        }
+        if food_taken {
+            self.respawn_food();
+        }
+        if self.snakes.iter().filter(|snake| snake.is_alive()).count() <= 1 {
+            self.game_over = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const A: Seat = Seat::PLAYER;
+    const B: Seat = Seat::BOT;
+
+    fn p(x: i32, y: i32) -> Point {
+        Point { x, y }
+    }
+
+    // A competitive game on an open 10x10 board with the food and the block out of the way
+    fn arena(head_on: HeadOn, food_tie: FoodTie) -> Game {
+        let settings = Settings { grid_width: 10, grid_height: 10, head_on, food_tie, ..Settings::default() };
+        let mut game = Game::new(Mode::Competitive, 1, &settings);
+        game.food = p(0, 9);
+        game.block = p(9, 9);
+        game
+    }
+
+    // Puts the snake in `seat` on `body`, head first
+    fn place(game: &mut Game, seat: Seat, body: &[Point]) {
+        for cell in game.snakes[seat.0].body.clone() {
+            game.board.vacate(cell);
+        }
+        for &cell in body {
+            game.board.occupy(cell, seat);
+        }
+        game.snakes[seat.0].body = body.iter().copied().collect();
+    }
+
+    fn step(game: &mut Game, a: Direction, b: Direction) -> StepOutcome {
+        game.advance(Moves::pair(Some(a), Some(b)))
+    }
+
+    fn rules(game: &Game) -> Vec<Rule> {
+        game.rulings().iter().map(|ruling| ruling.rule).collect()
+    }
+
+    #[test]
+    fn equally_long_snakes_meeting_on_a_cell_both_die() {
+        for (head_on, rule) in [(HeadOn::LongerWins, Rule::EqualLength), (HeadOn::BothDie, Rule::BothDie)] {
+            let mut game = arena(head_on, FoodTie::Nobody);
+            place(&mut game, A, &[p(2, 5)]);
+            place(&mut game, B, &[p(4, 5)]);
+            assert_eq!(step(&mut game, Direction::Right, Direction::Left), StepOutcome::GameOver);
+            assert_eq!((game.death(A), game.death(B)), (Some(Death::HeadOn), Some(Death::HeadOn)));
+            assert_eq!(game.rulings(), [Ruling { tick: 1, cell: p(3, 5), seats: vec![A, B], rule, winner: None }]);
+        }
+    }
+
+    #[test]
+    fn the_longer_snake_wins_a_head_on_unless_both_die() {
+        let mut game = arena(HeadOn::LongerWins, FoodTie::Nobody);
+        place(&mut game, A, &[p(2, 5), p(1, 5), p(0, 5)]);
+        place(&mut game, B, &[p(4, 5)]);
+        step(&mut game, Direction::Right, Direction::Left);
+        assert_eq!((game.death(A), game.death(B)), (None, Some(Death::HeadOn)));
+        assert_eq!(game.rulings()[0].winner, Some(A));
+        assert_eq!(game.player_snake().iter().copied().collect::<Vec<_>>(), [p(3, 5), p(2, 5), p(1, 5)]);
+        assert_eq!(game.board().occupant(p(3, 5)), Some(A));
+
+        let mut game = arena(HeadOn::BothDie, FoodTie::Nobody);
+        place(&mut game, A, &[p(2, 5), p(1, 5), p(0, 5)]);
+        place(&mut game, B, &[p(4, 5)]);
+        step(&mut game, Direction::Right, Direction::Left);
+        assert_eq!((game.death(A), game.death(B)), (Some(Death::HeadOn), Some(Death::HeadOn)));
+        assert_eq!(rules(&game), [Rule::BothDie]);
+    }
+
+    #[test]
+    fn snakes_swapping_cells_collide_head_on() {
+        for (head_on, rule) in [(HeadOn::LongerWins, Rule::EqualLength), (HeadOn::BothDie, Rule::BothDie)] {
+            let mut game = arena(head_on, FoodTie::Nobody);
+            place(&mut game, A, &[p(5, 5)]);
+            place(&mut game, B, &[p(6, 5)]);
+            assert_eq!(step(&mut game, Direction::Right, Direction::Left), StepOutcome::GameOver);
+            assert_eq!((game.death(A), game.death(B)), (Some(Death::HeadOn), Some(Death::HeadOn)));
+            assert_eq!(game.rulings(), [Ruling { tick: 1, cell: p(6, 5), seats: vec![A, B], rule, winner: None }]);
+        }
+    }
+
+    #[test]
+    fn the_longer_snake_wins_a_swap() {
+        let mut game = arena(HeadOn::LongerWins, FoodTie::Nobody);
+        place(&mut game, A, &[p(5, 5)]);
+        place(&mut game, B, &[p(6, 5), p(7, 5), p(8, 5)]);
+        step(&mut game, Direction::Right, Direction::Left);
+        assert_eq!((game.death(A), game.death(B)), (Some(Death::HeadOn), None));
+        assert_eq!(rules(&game), [Rule::LongerWins]);
+        assert_eq!(game.bot_snake().iter().copied().collect::<Vec<_>>(), [p(5, 5), p(6, 5), p(7, 5)]);
+        assert_eq!(game.board().occupant(p(5, 5)), Some(B));
+        assert_eq!(game.board().occupant(p(8, 5)), None);
+    }
+
+    #[test]
+    fn a_snake_can_follow_a_tail_that_moves_away() {
+        for head_on in HeadOn::ALL {
+            let mut game = arena(head_on, FoodTie::Nobody);
+            place(&mut game, A, &[p(3, 5), p(2, 5)]);
+            place(&mut game, B, &[p(4, 4), p(4, 5)]);
+            assert_eq!(step(&mut game, Direction::Right, Direction::Up), StepOutcome::Running);
+            assert_eq!((game.death(A), game.death(B)), (None, None));
+            assert!(game.rulings().is_empty());
+            assert_eq!(game.board().occupant(p(4, 5)), Some(A));
+            assert_eq!(game.board().occupant(p(2, 5)), None);
+        }
+    }
+
+    #[test]
+    fn a_snake_that_eats_keeps_its_tail() {
+        for head_on in HeadOn::ALL {
+            let mut game = arena(head_on, FoodTie::Nobody);
+            game.food = p(4, 3);
+            place(&mut game, A, &[p(3, 5), p(2, 5)]);
+            place(&mut game, B, &[p(4, 4), p(4, 5)]);
+            assert_eq!(step(&mut game, Direction::Right, Direction::Up), StepOutcome::GameOver);
+            assert_eq!((game.death(A), game.death(B)), (Some(Death::Opponent), None));
+            assert_eq!(game.bot_score(), FOOD_POINTS);
+            assert_eq!(game.bot_snake().len(), 3);
+            assert!(game.rulings().is_empty());
+        }
+    }
+
+    #[test]
+    fn food_reached_by_snakes_that_all_die_goes_by_the_food_tie_rule() {
+        for head_on in HeadOn::ALL {
+            for (food_tie, rule, points) in [(FoodTie::Nobody, Rule::FoodUnclaimed, 0), (FoodTie::Shared, Rule::FoodShared, FOOD_POINTS)] {
+                let mut game = arena(head_on, food_tie);
+                game.food = p(3, 5);
+                place(&mut game, A, &[p(2, 5)]);
+                place(&mut game, B, &[p(4, 5)]);
+                step(&mut game, Direction::Right, Direction::Left);
+                assert_eq!((game.death(A), game.death(B)), (Some(Death::HeadOn), Some(Death::HeadOn)));
+                assert_eq!(rules(&game)[1..], [rule]);
+                assert_eq!(game.rulings()[1].seats, [A, B]);
+                assert_eq!((game.player_score(), game.bot_score()), (points, points));
+                assert_eq!(game.food() == p(3, 5), food_tie == FoodTie::Nobody);
+            }
+        }
+    }
+
+    #[test]
+    fn snakes_cross_the_edge_of_a_wrapping_board() {
+        let settings = Settings { grid_width: 10, grid_height: 10, wrap: true, ..Settings::default() };
+        let mut game = Game::new(Mode::Competitive, 1, &settings);
+        game.food = p(0, 9);
+        game.block = p(9, 9);
+        place(&mut game, A, &[p(9, 5), p(8, 5)]);
+        place(&mut game, B, &[p(4, 0), p(4, 1)]);
+        assert_eq!(step(&mut game, Direction::Right, Direction::Up), StepOutcome::Running);
+        assert_eq!(game.player_snake().iter().copied().collect::<Vec<_>>(), [p(0, 5), p(9, 5)]);
+        assert_eq!(game.snakes()[B.0].body.front(), Some(&p(4, 9)));
+        assert_eq!((game.death(A), game.death(B)), (None, None));
+    }
+
+    #[test]
+    fn a_snake_cannot_reverse_onto_its_own_tail() {
+        let mut game = arena(HeadOn::LongerWins, FoodTie::Nobody);
+        place(&mut game, A, &[p(3, 5), p(2, 5)]);
+        place(&mut game, B, &[p(6, 2)]);
+        assert_eq!(step(&mut game, Direction::Left, Direction::Up), StepOutcome::GameOver);
+        assert_eq!((game.death(A), game.death(B)), (Some(Death::Itself), None));
+
+        let mut game = Game::new(Mode::Single, 1, &Settings { grid_width: 10, grid_height: 10, ..Settings::default() });
+        game.food = p(0, 9);
+        game.block = p(9, 9);
+        place(&mut game, A, &[p(3, 5), p(2, 5)]);
+        assert_eq!(game.advance(Moves::pair(Some(Direction::Left), None)), StepOutcome::GameOver);
+        assert_eq!(game.death(A), Some(Death::Itself));
+    }
+
+    #[test]
+    fn the_block_moves_once_every_head_is_on_the_board() {
+        let settings = Settings { grid_width: 4, grid_height: 4, ..Settings::default() };
+        for seed in 0..50 {
+            let mut game = Game::new(Mode::Competitive, seed, &settings);
+            game.food = p(3, 3);
+            game.block = p(0, 0);
+            place(&mut game, A, &[]);
+            place(&mut game, B, &[]);
+            place(&mut game, A, &[p(1, 0), p(2, 0), p(3, 0), p(3, 1), p(2, 1), p(1, 1)]);
+            place(&mut game, B, &[p(0, 2), p(0, 3)]);
+            step(&mut game, Direction::Left, Direction::Right);
+            assert_eq!((game.player_snake().len(), game.bot_snake().len()), (3, 2));
+            assert_eq!(game.board().occupant(game.block()), None, "seed {}", seed);
+        }
+    }
+
+    #[test]
+    fn the_winner_of_a_head_on_on_the_food_eats_it() {
+        for food_tie in FoodTie::ALL {
+            let mut game = arena(HeadOn::LongerWins, food_tie);
+            game.food = p(3, 5);
+            place(&mut game, A, &[p(2, 5)]);
+            place(&mut game, B, &[p(4, 5), p(5, 5)]);
+            step(&mut game, Direction::Right, Direction::Left);
+            assert_eq!((game.death(A), game.death(B)), (Some(Death::HeadOn), None));
+            assert_eq!(rules(&game), [Rule::LongerWins]);
+            assert_eq!((game.player_score(), game.bot_score()), (0, FOOD_POINTS));
+            assert_eq!(game.bot_snake().len(), 3);
+            assert_ne!(game.food(), p(3, 5));
+        }
+    }
+}
@@ -0,0 +1,427 @@
+// Levels for Rusty Snake.
+// A level fixes the board size, the walls, where food appears and where each snake starts. Levels
+// are TOML files with the board drawn as text, `#` for a wall, `*` for a food spawner and `.` for
+// any other open cell:
+//
+//     title = "Pillars"
+//     single = { at = [10, 6], heading = "right" }
+//     player = { at = [4, 6], heading = "right" }
+//     bot = { at = [15, 6], heading = "left" }
+//     map = """
+//     ....................
+//     ...##..........##...
+//     .........*..........
+//     ....................
+//     """
+//
+// Every field but `map` may be left out. The title falls back to the file name and the starts to
+// the ones of an open board. A few levels ship with the game, more can be dropped into LEVEL_DIR.
+
+use serde::Deserialize;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use crate::game::{Direction, Point};
+use crate::settings::{MAX_GRID_SIZE, MIN_GRID_SIZE, Settings};
+
+// Directory searched for levels besides the bundled ones
+pub const LEVEL_DIR: &str = "levels";
+
+// Name of the wall-less board every game is played on unless a level is picked
+pub const OPEN: &str = "open";
+
+const BUNDLED: [(&str, &str); 4] = [
+    ("box", include_str!("../levels/box.toml")),
+    ("pillars", include_str!("../levels/pillars.toml")),
+    ("cross", include_str!("../levels/cross.toml")),
+    ("rooms", include_str!("../levels/rooms.toml")),
+];
+
+// Where a snake starts and which way it heads first
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Start {
+    pub at: Point,
+    pub heading: Direction,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Level {
+    // File name without the extension, replays refer to the level by it
+    pub name: String,
+    pub title: String,
+    pub width: i32,
+    pub height: i32,
+    pub walls: Vec<Point>,
+    // Cells food spawns on while one of them is free, anywhere when there are none
+    pub food: Vec<Point>,
+    // The snake of a single player game
+    pub single: Start,
+    // The two snakes of a competitive game
+    pub player: Start,
+    pub bot: Start,
+}
+
+#[derive(Debug)]
+pub enum LevelError {
+    Io(PathBuf, io::Error),
+    Parse(String, toml::de::Error),
+    Invalid(String, String),
+    Unknown(String),
+}
+
+impl fmt::Display for LevelError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LevelError::Io(path, e) => write!(f, "could not read {}: {}", path.display(), e),
+            LevelError::Parse(name, e) => write!(f, "could not parse level {}: {}", name, e),
+            LevelError::Invalid(name, message) => write!(f, "invalid level {}: {}", name, message),
+            LevelError::Unknown(name) => write!(f, "no level called {}, not bundled and not in {}/", name, LEVEL_DIR),
+        }
+    }
+}
+
+impl std::error::Error for LevelError {}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LevelFile {
+    title: Option<String>,
+    map: String,
+    single: Option<StartFile>,
+    player: Option<StartFile>,
+    bot: Option<StartFile>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StartFile {
+    at: [i32; 2],
+    heading: Option<Direction>,
+}
+
+impl Level {
+    // A board without walls, with the snakes starting where they always have
+    pub fn open(width: i32, height: i32) -> Level {
+        let start = |x: i32, heading: Direction| Start { at: Point { x, y: height / 2 }, heading };
+        Level {
+            name: OPEN.to_string(),
+            title: "Open board".to_string(),
+            width,
+            height,
+            walls: vec![],
+            food: vec![],
+            single: start(width / 2, Direction::Right),
+            player: start(width / 4, Direction::Right),
+            bot: start(3 * width / 4, Direction::Left),
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.name == OPEN
+    }
+
+    pub fn parse(name: &str, text: &str) -> Result<Level, LevelError> {
+        let invalid = |message: String| LevelError::Invalid(name.to_string(), message);
+        let file: LevelFile = toml::from_str(text).map_err(|e| LevelError::Parse(name.to_string(), e))?;
+
+        let rows: Vec<&str> = file.map.lines().map(str::trim).filter(|row| !row.is_empty()).collect();
+        let height = rows.len() as i32;
+        let width = rows.first().map_or(0, |row| row.chars().count() as i32);
+        for (size, value) in [("width", width), ("height", height)] {
+            if !(MIN_GRID_SIZE..=MAX_GRID_SIZE).contains(&value) {
+                return Err(invalid(format!("map {} must be between {} and {}, got {}", size, MIN_GRID_SIZE, MAX_GRID_SIZE, value)));
+            }
+        }
+        let mut walls = vec![];
+        let mut food = vec![];
+        for (y, row) in rows.iter().enumerate() {
+            if row.chars().count() as i32 != width {
+                return Err(invalid(format!("map row {} is not {} cells wide", y + 1, width)));
+            }
+            for (x, c) in row.chars().enumerate() {
+                match c {
+                    '#' => walls.push(Point { x: x as i32, y: y as i32 }),
+                    '*' => food.push(Point { x: x as i32, y: y as i32 }),
+                    '.' => {},
+                    _ => return Err(invalid(format!("unexpected {:?} in map row {}, use # for walls, * for food and . for open cells", c, y + 1))),
+                }
+            }
+        }
+
+        let open = Level::open(width, height);
+        let start = |start: Option<StartFile>, default: Start| match start {
+            Some(start) => Start { at: Point { x: start.at[0], y: start.at[1] }, heading: start.heading.unwrap_or(default.heading) },
+            None => default,
+        };
+        let level = Level {
+            name: name.to_string(),
+            title: file.title.unwrap_or_else(|| name.to_string()),
+            width,
+            height,
+            walls,
+            food,
+            single: start(file.single, open.single),
+            player: start(file.player, open.player),
+            bot: start(file.bot, open.bot),
+        };
+        level.validate().map_err(invalid)?;
+        Ok(level)
+    }
+
+    // Every start lies on an open cell and there is room left for the food and the block
+    pub fn validate(&self) -> Result<(), String> {
+        for (seat, start) in [("single", self.single), ("player", self.player), ("bot", self.bot)] {
+            let p = start.at;
+            if p.x < 0 || p.x >= self.width || p.y < 0 || p.y >= self.height {
+                return Err(format!("{} start {},{} is off the board", seat, p.x, p.y));
+            }
+            if self.walls.contains(&p) {
+                return Err(format!("{} start {},{} is on a wall", seat, p.x, p.y));
+            }
+        }
+        if self.player.at == self.bot.at {
+            return Err("player and bot start on the same cell".to_string());
+        }
+        if (self.width * self.height) as usize - self.walls.len() < 4 {
+            return Err("fewer than four open cells".to_string());
+        }
+        Ok(())
+    }
+
+    // The level in the file format, with the starts written out even when they are the defaults
+    pub fn to_toml(&self) -> String {
+        let mut text = format!("title = {}\n", toml::Value::String(self.title.clone()));
+        for (seat, start) in [("single", self.single), ("player", self.player), ("bot", self.bot)] {
+            text.push_str(&format!("{} = {{ at = [{}, {}], heading = \"{}\" }}\n", seat, start.at.x, start.at.y, start.heading.name()));
+        }
+        text.push_str("map = \"\"\"\n");
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let p = Point { x, y };
+                text.push(if self.walls.contains(&p) {
+                    '#'
+                } else if self.food.contains(&p) {
+                    '*'
+                } else {
+                    '.'
+                });
+            }
+            text.push('\n');
+        }
+        text.push_str("\"\"\"\n");
+        text
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, self.to_toml())
+    }
+
+    // Path a level called `name` is saved to
+    pub fn path(name: &str) -> PathBuf {
+        Path::new(LEVEL_DIR).join(format!("{}.toml", name))
+    }
+
+    pub fn is_bundled(name: &str) -> bool {
+        BUNDLED.iter().any(|(bundled, _)| *bundled == name)
+    }
+
+    // Turns the cell into a wall or back into an open cell. A wall replaces a food spawner.
+    pub fn set_wall(&mut self, p: Point, wall: bool) {
+        self.walls.retain(|w| *w != p);
+        if wall {
+            self.food.retain(|f| *f != p);
+            self.walls.push(p);
+        }
+    }
+
+    // Adds or removes a food spawner, never on a wall
+    pub fn set_food(&mut self, p: Point, food: bool) {
+        self.food.retain(|f| *f != p);
+        if food && !self.walls.contains(&p) {
+            self.food.push(p);
+        }
+    }
+
+    // Grows or shrinks the board from the bottom right corner.
+    // Walls and spawners that fall off are dropped, starts are pulled back onto the board.
+    pub fn resize(&mut self, width: i32, height: i32) {
+        let width = width.clamp(MIN_GRID_SIZE, MAX_GRID_SIZE);
+        let height = height.clamp(MIN_GRID_SIZE, MAX_GRID_SIZE);
+        let inside = |p: &Point| p.x < width && p.y < height;
+        self.walls.retain(inside);
+        self.food.retain(inside);
+        for start in [&mut self.single, &mut self.player, &mut self.bot] {
+            start.at = Point { x: start.at.x.min(width - 1), y: start.at.y.min(height - 1) };
+        }
+        self.width = width;
+        self.height = height;
+    }
+
+    // Where each of `snakes` snakes starts: the single start for one snake, the player and the bot
+    // start for two. Any further snake starts on the open cell farthest from those taken so far,
+    // heading for the middle of the board.
+    pub fn starts(&self, snakes: usize) -> Vec<Start> {
+        if snakes == 1 {
+            return vec![self.single];
+        }
+        let mut starts = vec![self.player, self.bot];
+        let middle = Point { x: self.width / 2, y: self.height / 2 };
+        while starts.len() < snakes {
+            let mut best: Option<(i32, Point)> = None;
+            for y in 0..self.height {
+                for x in 0..self.width {
+                    let p = Point { x, y };
+                    if self.walls.contains(&p) {
+                        continue;
+                    }
+                    let room = starts.iter().map(|start| (start.at.x - x).abs() + (start.at.y - y).abs()).min().unwrap_or(0);
+                    if best.is_none_or(|(most, _)| room > most) {
+                        best = Some((room, p));
+                    }
+                }
+            }
+            let Some((_, at)) = best.filter(|(room, _)| *room > 0) else {
+                break;
+            };
+            let (dx, dy) = (middle.x - at.x, middle.y - at.y);
+            let heading = match (dx.abs() >= dy.abs(), dx >= 0, dy >= 0) {
+                (true, true, _) => Direction::Right,
+                (true, false, _) => Direction::Left,
+                (false, _, true) => Direction::Down,
+                (false, _, false) => Direction::Up,
+            };
+            starts.push(Start { at, heading });
+        }
+        starts
+    }
+
+    // Every snake needs an open cell to start on, and the food and the block one each besides.
+    // A valid level always has room for two snakes.
+    pub fn room_for(&self, snakes: usize) -> Result<(), String> {
+        let open = (self.width * self.height) as usize - self.walls.len();
+        if open < snakes + 2 {
+            return Err(format!("level {} has {} open cells, too few for {} snakes, the food and the block", self.name, open, snakes));
+        }
+        Ok(())
+    }
+
+    // Loads a level file, named after the file
+    pub fn load(path: &Path) -> Result<Level, LevelError> {
+        let text = fs::read_to_string(path).map_err(|e| LevelError::Io(path.to_path_buf(), e))?;
+        let name = path.file_stem().map_or(String::new(), |stem| stem.to_string_lossy().into_owned());
+        Self::parse(&name, &text)
+    }
+
+    // The bundled level called `name`, then the one in LEVEL_DIR, then `name` read as a path
+    pub fn find(name: &str) -> Result<Level, LevelError> {
+        if let Some((_, text)) = BUNDLED.iter().find(|(bundled, _)| *bundled == name) {
+            return Self::parse(name, text);
+        }
+        let in_dir = Self::path(name);
+        if in_dir.exists() {
+            return Self::load(&in_dir);
+        }
+        if Path::new(name).exists() {
+            return Self::load(Path::new(name));
+        }
+        Err(LevelError::Unknown(name.to_string()))
+    }
+
+    pub fn bundled() -> Vec<Level> {
+        BUNDLED.iter().map(|(name, text)| Self::parse(name, text).expect("bundled levels are valid")).collect()
+    }
+
+    // Bundled levels followed by the readable ones in LEVEL_DIR, sorted by name.
+    // A file in LEVEL_DIR named like a bundled level is left out.
+    pub fn available() -> Vec<Level> {
+        let mut levels = Self::bundled();
+        let mut extra = vec![];
+        if let Ok(entries) = fs::read_dir(LEVEL_DIR) {
+            for path in entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()) {
+                if path.extension().is_some_and(|ext| ext == "toml") {
+                    if let Ok(level) = Self::load(&path) {
+                        if !levels.iter().any(|bundled| bundled.name == level.name) {
+                            extra.push(level);
+                        }
+                    }
+                }
+            }
+        }
+        extra.sort_by(|a, b| a.name.cmp(&b.name));
+        levels.extend(extra);
+        levels
+    }
+
+    // Settings with the board size of this level
+    pub fn settings(&self, settings: &Settings) -> Settings {
+        Settings { grid_width: self.width, grid_height: self.height, ..*settings }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn p(x: i32, y: i32) -> Point {
+        Point { x, y }
+    }
+
+    // The error `parse` gives for `text`, which has to be a valid file but not a valid level
+    fn invalid(text: &str) -> String {
+        match Level::parse("test", text) {
+            Err(LevelError::Invalid(name, message)) if name == "test" => message,
+            other => panic!("expected an invalid level, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn levels_come_back_the_same_from_their_file() {
+        for level in Level::bundled() {
+            assert_eq!(Level::parse(&level.name, &level.to_toml()).unwrap(), level);
+        }
+        let mut level = Level::open(7, 5);
+        level.title = "Quote \" and # inside".to_string();
+        level.set_wall(p(3, 1), true);
+        level.set_food(p(6, 4), true);
+        level.bot.heading = Direction::Up;
+        assert_eq!(Level::parse(OPEN, &level.to_toml()).unwrap(), level);
+    }
+
+    #[test]
+    fn a_map_alone_gives_the_starts_of_an_open_board() {
+        let level = Level::parse("small", "map = \"\"\"\n.....\n.#...\n....*\n.....\n.....\n\"\"\"\n").unwrap();
+        let open = Level::open(5, 5);
+        assert_eq!((level.title.as_str(), level.width, level.height), ("small", 5, 5));
+        assert_eq!((level.walls, level.food), (vec![p(1, 1)], vec![p(4, 2)]));
+        assert_eq!((level.single, level.player, level.bot), (open.single, open.player, open.bot));
+    }
+
+    #[test]
+    fn broken_levels_are_refused() {
+        let map = |rows: &str| format!("map = \"\"\"\n{}\"\"\"\n", rows);
+        let board = ".....\n.....\n.....\n.....\n.....\n";
+        assert!(invalid(&map("....\n....\n....\n....\n")).contains("map width must be between"));
+        assert!(invalid(&map(".....\n.....\n....\n.....\n.....\n")).contains("map row 3 is not 5 cells wide"));
+        assert!(invalid(&map(".....\n..x..\n.....\n.....\n.....\n")).contains("unexpected 'x' in map row 2"));
+        assert!(invalid(&format!("single = {{ at = [9, 0] }}\n{}", map(board))).contains("single start 9,0 is off the board"));
+        assert!(invalid(&format!("bot = {{ at = [0, 0] }}\n{}", map("#....\n.....\n.....\n.....\n.....\n"))).contains("bot start 0,0 is on a wall"));
+        assert!(invalid(&format!("player = {{ at = [3, 2] }}\n{}", map(board))).contains("player and bot start on the same cell"));
+        assert!(matches!(Level::parse("test", "map = 5"), Err(LevelError::Parse(..))));
+        assert!(matches!(Level::parse("test", "title = \"no map\""), Err(LevelError::Parse(..))));
+    }
+
+    #[test]
+    fn room_for_counts_a_cell_per_snake_besides_the_food_and_the_block() {
+        let mut level = Level::open(5, 5);
+        for y in 0..5 {
+            for x in 0..5 {
+                level.set_wall(p(x, y), y > 0);
+            }
+        }
+        assert_eq!(level.room_for(3), Ok(()));
+        assert_eq!(level.room_for(4), Err("level open has 5 open cells, too few for 4 snakes, the food and the block".to_string()));
+        assert_eq!(Level::open(5, 5).room_for(23), Ok(()));
+        assert!(Level::open(5, 5).room_for(24).is_err());
+    }
+}
@@ -0,0 +1,18 @@
+// Rusty Snake as a library.
+// Holds the rules of the game so they can be driven without opening a window.
+
+pub mod battlesnake;
+pub mod board;
+pub mod bot;
+pub mod env;
+pub mod external;
+pub mod game;
+pub mod level;
+pub mod net;
+pub mod replay;
+pub mod session;
+pub mod settings;
+pub mod simulate;
+pub mod spectate;
+pub mod strategy;
+pub mod tournament;
@@ -1,405 +1,634 @@
-// Importing local settings for Rusty Snake
-mod settings;
+// Window frontend for Rusty Snake, the rules live in the library crate
+mod cli;
+mod editor;
 
+use clap::Parser;
+use cli::{Cli, Command};
+use editor::{Action, Editor};
 use ggez::{Context, ContextBuilder, GameResult, event, graphics, timer};
-use ggez::event::{KeyCode, KeyMods};
-use rand::Rng;
-use std::cmp;
-use settings::*;
-
-#[derive(Clone, Copy, PartialEq, Eq, Debug)]
-struct Point {
-    x: i32,
-    y: i32,
-}
+use ggez::event::{Button, GamepadId, KeyCode, KeyMods, MouseButton};
+use rusty_snake::game::{Death, Direction, Game, Inputs, MAX_SNAKES, Mode, PERFECT_GAME_BONUS, Point, Seat, StepOutcome};
+use rusty_snake::level::Level;
+use rusty_snake::net::{DEFAULT_PORT, Peer};
+use rusty_snake::spectate::{Broadcast, DEFAULT_SPECTATE_PORT, Spectator};
+use rusty_snake::replay::{Playback, Replay};
+use rusty_snake::session::Session;
+use rusty_snake::settings::Settings;
+use rusty_snake::simulate::Simulation;
+use rusty_snake::tournament::{Format, Tournament};
+use rusty_snake::strategy::{Human, Strategy, StrategyKind};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+const REPLAY_DIR: &str = "replays";
+const REPLAY_SPEEDS: [f32; 6] = [0.25, 0.5, 1.0, 2.0, 4.0, 8.0];
+// What the two snakes of a competitive game are called, against the bot and between two people
+const BOT_NAMES: [&str; 2] = ["Player", "Bot"];
+const TWO_PLAYER_NAMES: [&str; 2] = ["Player 1", "Player 2"];
+const ONLINE_NAMES: [&str; 2] = ["Host", "Guest"];
+// Colour of the snake in each seat, arena snakes are named after theirs
+const SNAKE_COLORS: [(&str, graphics::Color); MAX_SNAKES] = [
+    ("White", graphics::Color::new(1.0, 1.0, 1.0, 1.0)),
+    ("Yellow", graphics::Color::new(1.0, 1.0, 0.0, 1.0)),
+    ("Cyan", graphics::Color::new(0.0, 1.0, 1.0, 1.0)),
+    ("Magenta", graphics::Color::new(1.0, 0.0, 1.0, 1.0)),
+    ("Blue", graphics::Color::new(0.3, 0.5, 1.0, 1.0)),
+    ("Red", graphics::Color::new(1.0, 0.3, 0.3, 1.0)),
+    ("Purple", graphics::Color::new(0.6, 0.4, 1.0, 1.0)),
+    ("Pink", graphics::Color::new(1.0, 0.6, 0.8, 1.0)),
+];
+// Snakes picked by the arena entry of the menu, the human takes the first seat
+const MENU_ARENA: [StrategyKind; 4] = [StrategyKind::Human, StrategyKind::Greedy, StrategyKind::Pathfinder, StrategyKind::Greedy];
 
 #[derive(PartialEq)]
 // Checking state for Gameplay
 // This could be single-mode, bot, or competitive
 enum AppState {
     Menu,
-    Playing,    
-    Competitive, 
+    Playing,
+    Competitive,
+    Replay,
     GameOver,
+    // The snake filled the whole board
+    PerfectGame,
+    LevelSelect,
+    Editor,
+    // Watching a game broadcast from elsewhere
+    Spectating,
+}
+
+// The list shown in AppState::LevelSelect
+struct LevelMenu {
+    levels: Vec<Arc<Level>>,
+    selected: usize,
+    // The picked level is opened in the editor instead of being played
+    for_editor: bool,
+}
+
+// A game against another machine, played in AppState::Competitive
+struct Online {
+    peer: Peer,
+    // Steers the snake of this side
+    strategy: Box<dyn Strategy>,
+}
+
+// Playback controls for AppState::Replay
+struct ReplayView {
+    playback: Playback,
+    paused: bool,
+    // Index into REPLAY_SPEEDS
+    speed: usize,
 }
 
 struct MainState {
-    snake: Vec<Point>,
-    snake_dir: Point,
-    score: i32,
-    auto_play: bool,
-
-    player_snake: Vec<Point>,
-    player_snake_dir: Point,
-    bot_snake: Vec<Point>,
-    player_score: i32,
-    bot_score: i32,
-
-    food: Point,
-    block: Point,
+    settings: Settings,
+    // Board new games are played on, its size wins over the one in the settings
+    level: Arc<Level>,
+    level_menu: Option<LevelMenu>,
+    // Kept while a level is being edited, test plays return to it
+    editor: Option<Editor>,
+    session: Session,
+    // Fixed seed from the command line, a fresh one is rolled per game otherwise
+    seed: Option<u64>,
+    inputs: Inputs,
+    // What the snakes of the game on screen are called, by seat
+    names: Vec<String>,
+    // A human plays the bot, the game over screen then speaks to the player
+    versus_bot: bool,
+    // Replaces the session while a networked game is on
+    online: Option<Online>,
+    // Spectators watching the games of this window
+    broadcast: Option<Broadcast>,
+    spectator: Option<Spectator>,
+    recording: Replay,
+    replay: Option<ReplayView>,
+    message: Option<String>,
     timer: f32,
-    game_over: bool,
+    // Seconds spent in the current game
+    play_time: f32,
     app_state: AppState,
     fullscreen: bool,
 }
 
 impl MainState {
-    fn new(_ctx: &mut Context) -> GameResult<MainState> {
-        let mut s = MainState {
-            snake: vec![],
-            snake_dir: Point { x: 1, y: 0 },
-            score: 0,
-            auto_play: false,
-
-            player_snake: vec![],
-            player_snake_dir: Point { x: 1, y: 0 },
-            bot_snake: vec![],
-            player_score: 0,
-            bot_score: 0,
-
-            food: Point { x: 0, y: 0 },
-            block: Point { x: 0, y: 0 },
+    fn new(_ctx: &mut Context, settings: Settings, level: Option<Level>, seed: Option<u64>) -> GameResult<MainState> {
+        let level = Arc::new(level.unwrap_or_else(|| Level::open(settings.grid_width, settings.grid_height)));
+        let game = Game::on_level(Mode::Single, 0, &settings, level.clone());
+        let s = MainState {
+            settings,
+            level,
+            level_menu: None,
+            editor: None,
+            recording: Replay::new(&game),
+            session: Session::new(game, Box::new(Human), None),
+            seed,
+            inputs: Inputs::default(),
+            names: seat_names(&[StrategyKind::Human]),
+            versus_bot: false,
+            online: None,
+            broadcast: None,
+            spectator: None,
+            replay: None,
+            message: None,
             timer: 0.0,
-            game_over: false,
+            play_time: 0.0,
             app_state: AppState::Menu,
             fullscreen: false,
         };
-        s.reset();
         Ok(s)
     }
 
-    fn reset(&mut self) {
-        self.game_over = false;
-        self.timer = 0.0;
-        match self.app_state {
-            AppState::Competitive => {
-                self.player_snake = vec![Point { x: GRID_WIDTH / 4, y: GRID_HEIGHT / 2 }];
-                self.player_snake_dir = Point { x: 1, y: 0 };
-                self.bot_snake = vec![Point { x: 3 * GRID_WIDTH / 4, y: GRID_HEIGHT / 2 }];
-                self.player_score = 0;
-                self.bot_score = 0;
-                let obstacles: Vec<Point> = self.player_snake
-                    .iter()
-                    .chain(self.bot_snake.iter())
-                    .copied()
-                    .collect();
-                self.food = Self::spawn_food(&obstacles);
-                self.block = Self::spawn_block(&obstacles, self.food);
+    // Starts a single game when `bot` is None, a competitive one otherwise
+    fn start(&mut self, player: &StrategyKind, bot: Option<&StrategyKind>) {
+        let snakes: Vec<StrategyKind> = std::iter::once(player).chain(bot).cloned().collect();
+        self.start_game(&snakes);
+    }
+
+    // Starts a game with a snake for each strategy, a single player game for just one
+    fn start_game(&mut self, snakes: &[StrategyKind]) {
+        let settings = &self.level.settings(&self.settings);
+        let strategies = self.level.room_for(snakes.len()).and_then(|()| {
+            snakes.iter().map(|kind| kind.build(settings, &self.level)).collect::<Result<Vec<_>, _>>().map_err(|e| e.to_string())
+        });
+        let strategies = match strategies {
+            Ok(strategies) => strategies,
+            Err(e) => {
+                self.message = Some(format!("Cannot start the game: {}", e));
+                self.app_state = AppState::Menu;
+                return;
             },
-            _ => {
-                self.snake = vec![Point { x: GRID_WIDTH / 2, y: GRID_HEIGHT / 2 }];
-                self.snake_dir = Point { x: 1, y: 0 };
-                self.score = 0;
-                self.food = Self::spawn_food(&self.snake);
-                self.block = Self::spawn_block(&self.snake, self.food);
-            }
-        }
+        };
+        let mode = if snakes.len() > 1 { Mode::Competitive } else { Mode::Single };
+        let seed = self.seed.unwrap_or_else(rand::random);
+        let game = match mode {
+            Mode::Single => Game::on_level(mode, seed, settings, self.level.clone()),
+            Mode::Competitive => Game::arena(seed, settings, self.level.clone(), snakes.len()),
+        };
+        self.recording = Replay::new(&game);
+        self.session = Session::arena(game, strategies);
+        self.inputs = Inputs::default();
+        self.names = seat_names(snakes);
+        self.versus_bot = matches!(snakes, [StrategyKind::Human, bot] if *bot != StrategyKind::Human);
+        self.announce();
+        self.message = None;
+        self.timer = 0.0;
+        self.play_time = 0.0;
+        self.app_state = match mode {
+            Mode::Competitive => AppState::Competitive,
+            Mode::Single => AppState::Playing,
+        };
     }
 
-    fn spawn_food(obstacles: &Vec<Point>) -> Point {
-        let mut rng = rand::thread_rng();
-        loop {
-            let point = Point {
-                x: rng.gen_range(0..GRID_WIDTH),
-                y: rng.gen_range(0..GRID_HEIGHT),
-            };
-            if !obstacles.contains(&point) {
-                return point;
-            }
-        }
+    // Lists the open board and every level that can be loaded, starting on the current one
+    fn choose_level(&mut self, for_editor: bool) {
+        let mut levels = vec![Arc::new(Level::open(self.settings.grid_width, self.settings.grid_height))];
+        levels.extend(Level::available().into_iter().map(Arc::new));
+        let selected = levels.iter().position(|level| level.name == self.level.name).unwrap_or(0);
+        self.level_menu = Some(LevelMenu { levels, selected, for_editor });
+        self.message = None;
+        self.app_state = AppState::LevelSelect;
     }
 
-    fn spawn_block(obstacles: &Vec<Point>, food: Point) -> Point {
-        let mut rng = rand::thread_rng();
-        loop {
-            let point = Point {
-                x: rng.gen_range(0..GRID_WIDTH),
-                y: rng.gen_range(0..GRID_HEIGHT),
-            };
-            if !obstacles.contains(&point) && point != food {
-                return point;
-            }
-        }
+    fn edit(&mut self, level: &Level) {
+        self.editor = Some(Editor::new(level));
+        self.message = None;
+        self.app_state = AppState::Editor;
     }
 
-    fn choose_move(&self) -> Option<Point> {
-        let head = self.snake[0];
-        let possible_moves = vec![
-            ("UP", Point { x: head.x, y: head.y - 1 }),
-            ("DOWN", Point { x: head.x, y: head.y + 1 }),
-            ("LEFT", Point { x: head.x - 1, y: head.y }),
-            ("RIGHT", Point { x: head.x + 1, y: head.y }),
-        ];
-        let mut safe_moves = vec![];
-        for (_name, p) in possible_moves {
-            if p.x < 0 || p.x >= GRID_WIDTH || p.y < 0 || p.y >= GRID_HEIGHT {
-                continue;
-            }
-            if self.snake.contains(&p) {
-                continue;
-            }
-            safe_moves.push(p);
+    // Plays the level in the editor as it is, the editor stays open for when the game ends
+    fn test_level(&mut self, versus: bool) {
+        let Some(editor) = self.editor.as_mut() else {
+            return;
+        };
+        if let Err(e) = editor.level.validate() {
+            editor.message = Some(format!("Cannot play the level: {}", e));
+            return;
         }
-        if safe_moves.is_empty() {
-            return None;
+        self.level = Arc::new(editor.level.clone());
+        self.start(&StrategyKind::Human, versus.then_some(&StrategyKind::Greedy));
+    }
+
+    fn go_online(&mut self, peer: Peer, strategy: Box<dyn Strategy>) {
+        self.recording = Replay::new(peer.game());
+        self.online = Some(Online { peer, strategy });
+        self.names = ONLINE_NAMES.map(String::from).to_vec();
+        self.versus_bot = false;
+        self.announce();
+        self.inputs = Inputs::default();
+        self.message = None;
+        self.timer = 0.0;
+        self.play_time = 0.0;
+        self.app_state = AppState::Competitive;
+    }
+
+    // Tells the spectators a new game started
+    fn announce(&self) {
+        if let Some(broadcast) = &self.broadcast {
+            broadcast.start_game(self.game(), &self.names);
         }
-        safe_moves.sort_by_key(|p| (p.x - self.food.x).abs() + (p.y - self.food.y).abs());
-        Some(safe_moves[0])
-    }
-
-    fn choose_move_for_snake(snake: &Vec<Point>, obstacles: &Vec<Point>, food: Point) -> Option<Point> {
-        let head = snake[0];
-        let possible_moves = vec![
-            ("UP", Point { x: head.x, y: head.y - 1 }),
-            ("DOWN", Point { x: head.x, y: head.y + 1 }),
-            ("LEFT", Point { x: head.x - 1, y: head.y }),
-            ("RIGHT", Point { x: head.x + 1, y: head.y }),
-        ];
-        let mut safe_moves = vec![];
-        for (_name, p) in possible_moves {
-            if p.x < 0 || p.x >= GRID_WIDTH || p.y < 0 || p.y >= GRID_HEIGHT {
-                continue;
-            }
-            if snake.contains(&p) {
-                continue;
-            }
-            if obstacles.contains(&p) {
-                continue;
+    }
+
+    fn spectate(&mut self, spectator: Spectator) {
+        self.spectator = Some(spectator);
+        self.message = None;
+        self.app_state = AppState::Spectating;
+    }
+
+    fn tick_spectator(&mut self) {
+        let Some(spectator) = self.spectator.as_mut() else {
+            return;
+        };
+        // The stream keeps the last board on screen once it ends
+        if self.message.is_none() {
+            if let Err(e) = spectator.poll() {
+                self.message = Some(format!("The broadcast stopped: {}", e));
             }
-            safe_moves.push(p);
         }
-        if safe_moves.is_empty() {
-            return None;
+    }
+
+    // The game on screen, a networked one when there is one
+    fn game(&self) -> &Game {
+        match &self.online {
+            Some(online) => online.peer.game(),
+            None => self.session.game(),
         }
-        safe_moves.sort_by_key(|p| (p.x - food.x).abs() + (p.y - food.y).abs());
-        Some(safe_moves[0])
     }
 
-    fn update_single(&mut self) {
-        let new_head = if self.auto_play {
-            self.choose_move()
-        } else {
-            let head = self.snake[0];
-            Some(Point { x: head.x + self.snake_dir.x, y: head.y + self.snake_dir.y })
-        };
+    // Back to the editor after a test play, to the menu otherwise.
+    // A networked game is over for good, the other side has to host or join again.
+    fn leave_game(&mut self) {
+        self.online = None;
+        self.message = None;
+        self.app_state = if self.editor.is_some() { AppState::Editor } else { AppState::Menu };
+    }
 
-        if let Some(new_head) = new_head {
-            if new_head.x < 0 || new_head.x >= GRID_WIDTH || new_head.y < 0 || new_head.y >= GRID_HEIGHT || self.snake.contains(&new_head) {
-                self.game_over = true;
-                return;
-            }
-            self.snake.insert(0, new_head);
-            if new_head == self.food {
-                self.score += 10;
-                self.food = Self::spawn_food(&self.snake);
-            } else {
-                self.snake.pop();
-            }
-            if new_head == self.block {
-                let new_length = cmp::max(1, self.snake.len() / 2);
-                self.snake.truncate(new_length);
-                self.block = Self::spawn_block(&self.snake, self.food);
-                self.score = cmp::max(0, self.score - 5);
-            }
-        } else {
-            self.game_over = true;
-        }
+    // The board cell under a point of the window
+    fn cell_at(&self, ctx: &Context, x: f32, y: f32) -> Option<Point> {
+        let editor = self.editor.as_ref()?;
+        let (cell_size, offset) = layout(ctx, editor.level.width, editor.level.height);
+        Some(Point { x: ((x - offset.0) / cell_size).floor() as i32, y: ((y - offset.1) / cell_size).floor() as i32 })
     }
 
-    fn update_competitive(&mut self) {
-        let mut player_dead = false;
-        let mut bot_dead = false;
+    fn watch(&mut self, replay: Replay) {
+        self.replay = Some(ReplayView { playback: Playback::new(replay), paused: false, speed: 2 });
+        self.timer = 0.0;
+        self.app_state = AppState::Replay;
+    }
 
-        if let Some(player_head) = self.player_snake.first().copied() {
-            let new_head = Point { x: player_head.x + self.player_snake_dir.x, y: player_head.y + self.player_snake_dir.y };
-            if new_head.x < 0 || new_head.x >= GRID_WIDTH || new_head.y < 0 || new_head.y >= GRID_HEIGHT {
-                player_dead = true;
-            }
-            if self.player_snake.contains(&new_head) {
-                player_dead = true;
-            }
-            if self.bot_snake.contains(&new_head) {
-                player_dead = true;
+    fn tick(&mut self, ctx: &mut Context) {
+        let dt = timer::delta(ctx).as_secs_f32();
+        self.timer += dt;
+        self.play_time += dt;
+        if self.online.is_some() {
+            self.tick_online();
+            return;
+        }
+        if self.timer >= self.settings.move_interval {
+            self.timer -= self.settings.move_interval;
+            let inputs = std::mem::take(&mut self.inputs);
+            let outcome = self.session.step(inputs);
+            self.recording.record(self.session.game());
+            if let Some(broadcast) = &self.broadcast {
+                broadcast.record(self.session.game());
             }
-            if !player_dead {
-                self.player_snake.insert(0, new_head);
-                if new_head == self.food {
-                    self.player_score += 10;
-                    let obstacles: Vec<Point> = self.player_snake
-                        .iter()
-                        .chain(self.bot_snake.iter())
-                        .copied()
-                        .collect();
-                    self.food = Self::spawn_food(&obstacles);
-                    self.block = Self::spawn_block(&obstacles, self.food);
-                } else {
-                    self.player_snake.pop();
-                }
-                if new_head == self.block {
-                    let new_length = cmp::max(1, self.player_snake.len() / 2);
-                    self.player_snake.truncate(new_length);
-                    let obstacles: Vec<Point> = self.player_snake
-                        .iter()
-                        .chain(self.bot_snake.iter())
-                        .copied()
-                        .collect();
-                    self.block = Self::spawn_block(&obstacles, self.food);
-                    self.player_score = cmp::max(0, self.player_score - 5);
-                }
+            if outcome == StepOutcome::GameOver {
+                self.app_state = if self.session.game().is_perfect() { AppState::PerfectGame } else { AppState::GameOver };
             }
         }
+    }
 
-        let obstacles: Vec<Point> = self.bot_snake
-            .iter()
-            .chain(self.player_snake.iter())
-            .copied()
-            .collect();
-        let bot_new_head = Self::choose_move_for_snake(&self.bot_snake, &obstacles, self.food);
-        if let Some(new_head) = bot_new_head {
-            if new_head.x < 0 || new_head.x >= GRID_WIDTH || new_head.y < 0 || new_head.y >= GRID_HEIGHT {
-                bot_dead = true;
-            }
-            if self.bot_snake.contains(&new_head) {
-                bot_dead = true;
-            }
-            if self.player_snake.contains(&new_head) {
-                bot_dead = true;
-            }
-            if !bot_dead {
-                self.bot_snake.insert(0, new_head);
-                if new_head == self.food {
-                    self.bot_score += 10;
-                    let obs: Vec<Point> = self.player_snake
-                        .iter()
-                        .chain(self.bot_snake.iter())
-                        .copied()
-                        .collect();
-                    self.food = Self::spawn_food(&obs);
-                    self.block = Self::spawn_block(&obs, self.food);
-                } else {
-                    self.bot_snake.pop();
+    // The tick waits, and the game with it, until the other side's move is in
+    fn tick_online(&mut self) {
+        let Some(online) = self.online.as_mut() else {
+            return;
+        };
+        let interval = online.peer.settings().move_interval;
+        if self.timer < interval {
+            return;
+        }
+        let peer = &mut online.peer;
+        let mut result = Ok(());
+        if !peer.is_waiting() {
+            let inputs = std::mem::take(&mut self.inputs);
+            let dir = online.strategy.choose(&peer.game().view(peer.seat(), inputs.player));
+            result = peer.send_move(dir);
+        }
+        match result.and_then(|_| peer.poll()) {
+            Ok(Some(outcome)) => {
+                self.timer -= interval;
+                self.recording.record(peer.game());
+                if let Some(broadcast) = &self.broadcast {
+                    broadcast.record(peer.game());
                 }
-                if new_head == self.block {
-                    let new_length = cmp::max(1, self.bot_snake.len() / 2);
-                    self.bot_snake.truncate(new_length);
-                    let obs: Vec<Point> = self.player_snake
-                        .iter()
-                        .chain(self.bot_snake.iter())
-                        .copied()
-                        .collect();
-                    self.block = Self::spawn_block(&obs, self.food);
-                    self.bot_score = cmp::max(0, self.bot_score - 5);
+                if outcome == StepOutcome::GameOver {
+                    online.strategy.finish(&peer.game().view(peer.seat(), None));
+                    self.app_state = AppState::GameOver;
                 }
+            },
+            Ok(None) => {},
+            Err(e) => {
+                self.message = Some(format!("The networked game stopped: {}", e));
+                self.app_state = AppState::GameOver;
+            },
+        }
+    }
+
+    fn tick_replay(&mut self, ctx: &mut Context) {
+        let dt = timer::delta(ctx).as_secs_f32();
+        let Some(view) = self.replay.as_mut() else {
+            return;
+        };
+        if view.paused {
+            return;
+        }
+        self.timer += dt * REPLAY_SPEEDS[view.speed];
+        while self.timer >= self.settings.move_interval {
+            self.timer -= self.settings.move_interval;
+            if !view.playback.step_forward() {
+                view.paused = true;
+                self.timer = 0.0;
             }
-        } else {
-            bot_dead = true;
         }
+    }
+
+    fn save_replay(&mut self) {
+        let secs = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let path = PathBuf::from(REPLAY_DIR).join(format!("replay-{}-{}.txt", self.recording.seed, secs));
+        let result = std::fs::create_dir_all(REPLAY_DIR).and_then(|_| self.recording.save(&path));
+        self.message = Some(match result {
+            Ok(()) => format!("Replay saved to {}", path.display()),
+            Err(e) => format!("Could not save replay: {}", e),
+        });
+    }
+}
+
+fn draw_cell(ctx: &mut Context, cell: Point, cell_size: f32, offset: (f32, f32), color: graphics::Color) -> GameResult {
+    use graphics::{DrawMode, DrawParam, Mesh, Rect};
+    let rect = Rect::new(offset.0 + cell.x as f32 * cell_size, offset.1 + cell.y as f32 * cell_size, cell_size, cell_size);
+    let mesh = Mesh::new_rectangle(ctx, DrawMode::fill(), rect, color)?;
+    graphics::draw(ctx, &mesh, DrawParam::default())
+}
+
+// Outlines the board, which only has walls when it does not wrap around
+fn draw_walls(ctx: &mut Context, width: i32, height: i32, cell_size: f32, offset: (f32, f32)) -> GameResult {
+    use graphics::{Color, DrawMode, DrawParam, Mesh, Rect};
+    let rect = Rect::new(offset.0, offset.1, cell_size * width as f32, cell_size * height as f32);
+    let mesh = Mesh::new_rectangle(ctx, DrawMode::stroke(2.0), rect, Color::new(0.4, 0.4, 0.4, 1.0))?;
+    graphics::draw(ctx, &mesh, DrawParam::default())
+}
+
+// Size of a cell and the top left corner of a board centered in the window
+fn layout(ctx: &Context, grid_width: i32, grid_height: i32) -> (f32, (f32, f32)) {
+    let (screen_width, screen_height) = graphics::drawable_size(ctx);
+    let cell_size = (screen_width / grid_width as f32).min(screen_height / grid_height as f32);
+    let offset = ((screen_width - cell_size * grid_width as f32) / 2.0, (screen_height - cell_size * grid_height as f32) / 2.0);
+    (cell_size, offset)
+}
+
+// Draws the level being edited, the starts are marked with the way they head
+fn draw_editor(ctx: &mut Context, editor: &Editor, cell_size: f32, offset: (f32, f32)) -> GameResult {
+    use graphics::{Color, Text};
+    let level = &editor.level;
+    draw_walls(ctx, level.width, level.height, cell_size, offset)?;
+    for wall in &level.walls {
+        draw_cell(ctx, *wall, cell_size, offset, Color::new(0.4, 0.4, 0.4, 1.0))?;
+    }
+    for food in &level.food {
+        draw_cell(ctx, *food, cell_size, offset, Color::new(0.0, 0.5, 0.0, 1.0))?;
+    }
+    for (label, start, color) in [("S", level.single, Color::WHITE), ("P", level.player, Color::CYAN), ("B", level.bot, Color::YELLOW)] {
+        draw_cell(ctx, start.at, cell_size, offset, color)?;
+        let arrow = match start.heading {
+            Direction::Up => "^",
+            Direction::Down => "v",
+            Direction::Left => "<",
+            Direction::Right => ">",
+        };
+        let dest = ggez::mint::Point2 { x: offset.0 + start.at.x as f32 * cell_size, y: offset.1 + start.at.y as f32 * cell_size };
+        graphics::draw(ctx, &Text::new(format!("{}{}", label, arrow)), (dest, Color::BLACK))?;
+    }
+    graphics::draw(ctx, &Text::new(editor.status()), (ggez::mint::Point2 { x: 5.0, y: 5.0 }, Color::WHITE))
+}
 
-        if player_dead || bot_dead {
-            self.game_over = true;
+// Draws the board and the score line of a running game. Snakes that are out of a competitive
+// game stay where they died, faded.
+fn draw_game(ctx: &mut Context, game: &Game, names: &[String], cell_size: f32, offset: (f32, f32)) -> GameResult {
+    use graphics::{Color, Text};
+    let block_color = Color::new(1.0, 0.65, 0.0, 1.0);
+    if !game.wraps() {
+        draw_walls(ctx, game.width(), game.height(), cell_size, offset)?;
+    }
+    for wall in game.board().walls() {
+        draw_cell(ctx, *wall, cell_size, offset, Color::new(0.4, 0.4, 0.4, 1.0))?;
+    }
+    draw_cell(ctx, game.food(), cell_size, offset, Color::GREEN)?;
+    draw_cell(ctx, game.block(), cell_size, offset, block_color)?;
+    for (i, snake) in game.snakes().iter().enumerate() {
+        let mut color = SNAKE_COLORS[i].1;
+        if game.mode() == Mode::Competitive && !snake.is_alive() {
+            color.a = 0.35;
+        }
+        for segment in &snake.body {
+            draw_cell(ctx, *segment, cell_size, offset, color)?;
         }
     }
+    let score_text = match game.mode() {
+        Mode::Single => format!("Score: {}", game.score()),
+        Mode::Competitive => {
+            let scores: Vec<String> = game
+                .snakes()
+                .iter()
+                .enumerate()
+                .map(|(i, snake)| format!("{}: {}{}", seat_name(names, Seat(i)), snake.score, if snake.is_alive() { "" } else { " (out)" }))
+                .collect();
+            scores.join("   ")
+        },
+    };
+    graphics::draw(ctx, &Text::new(score_text), (ggez::mint::Point2 { x: 5.0, y: 5.0 }, Color::BLUE))
+}
+
+// Names for the snakes of a game between `snakes`, by seat
+fn seat_names(snakes: &[StrategyKind]) -> Vec<String> {
+    match snakes {
+        [_] => vec![BOT_NAMES[0].to_string()],
+        [StrategyKind::Human, StrategyKind::Human] => TWO_PLAYER_NAMES.map(String::from).to_vec(),
+        [_, _] => BOT_NAMES.map(String::from).to_vec(),
+        _ => snakes.iter().enumerate().map(|(i, kind)| format!("{} ({})", SNAKE_COLORS[i].0, kind.name())).collect(),
+    }
+}
+
+// Names for a game whose strategies are not known, like a replay
+fn default_names(seats: usize) -> Vec<String> {
+    match seats {
+        1 | 2 => BOT_NAMES[..seats].iter().map(|name| name.to_string()).collect(),
+        _ => SNAKE_COLORS[..seats].iter().map(|(color, _)| color.to_string()).collect(),
+    }
+}
+
+fn seat_name(names: &[String], seat: Seat) -> &str {
+    names.get(seat.0).map_or(SNAKE_COLORS[seat.0].0, String::as_str)
+}
+
+fn fate(death: Death) -> &'static str {
+    match death {
+        Death::Wall => "hit a wall",
+        Death::Itself => "ran into itself",
+        Death::Opponent => "ran into another snake",
+        Death::Trapped => "had no move left",
+        Death::HeadOn => "lost a head-on",
+    }
 }
 
 impl event::EventHandler for MainState {
     fn update(&mut self, ctx: &mut Context) -> GameResult {
         match self.app_state {
-            AppState::Playing => {
-                let dt = timer::delta(ctx).as_secs_f32();
-                self.timer += dt;
-                if self.timer >= MOVE_INTERVAL {
-                    self.timer -= MOVE_INTERVAL;
-                    self.update_single();
-                }
-                if self.game_over {
-                    self.app_state = AppState::GameOver;
-                }
-            },
-            AppState::Competitive => {
-                let dt = timer::delta(ctx).as_secs_f32();
-                self.timer += dt;
-                if self.timer >= MOVE_INTERVAL {
-                    self.timer -= MOVE_INTERVAL;
-                    self.update_competitive();
-                }
-                if self.game_over {
-                    self.app_state = AppState::GameOver;
-                }
-            },
+            AppState::Playing | AppState::Competitive => self.tick(ctx),
+            AppState::Spectating => self.tick_spectator(),
+            AppState::Replay => self.tick_replay(ctx),
             _ => {},
         }
         Ok(())
     }
 
     fn draw(&mut self, ctx: &mut Context) -> GameResult {
-        use graphics::{Color, DrawMode, DrawParam, Mesh, Rect, Text};
+        use graphics::{Color, Text};
         graphics::clear(ctx, Color::BLACK);
         let (screen_width, screen_height) = graphics::drawable_size(ctx);
-        let cell_size = (screen_width / GRID_WIDTH as f32).min(screen_height / GRID_HEIGHT as f32);
-        let grid_pixel_width = cell_size * GRID_WIDTH as f32;
-        let grid_pixel_height = cell_size * GRID_HEIGHT as f32;
-        let offset_x = (screen_width - grid_pixel_width) / 2.0;
-        let offset_y = (screen_height - grid_pixel_height) / 2.0;
+        // Replays, the editor and spectated games carry their own grid size
+        let (grid_width, grid_height) = match (&self.app_state, &self.replay, &self.editor, &self.spectator) {
+            (AppState::Replay, Some(view), _, _) => (view.playback.game().width(), view.playback.game().height()),
+            (AppState::Editor, _, Some(editor), _) => (editor.level.width, editor.level.height),
+            (AppState::Spectating, _, _, Some(spectator)) => (spectator.game().width(), spectator.game().height()),
+            _ => (self.game().width(), self.game().height()),
+        };
+        let (cell_size, offset) = layout(ctx, grid_width, grid_height);
 
         match self.app_state {
             AppState::Menu => {
-                let menu_text = Text::new("Self-Playing Snake\n\nPress 1 for Manual Play\nPress 2 for Self-Play\nPress 3 for Competitive Mode\n\nPress F11 to toggle Full Screen");
+                let mut menu = format!(
+                    "Self-Playing Snake\n\nPress 1 for Manual Play\nPress 2 for Self-Play\nPress 3 for Competitive Mode\nPress 4 to Watch the Last Game\nPress 5 for Perfect Self-Play (Hamiltonian Cycle)\nPress 6 to Choose a Level (now {})\nPress 7 to Edit Levels\nPress 8 for Two Players (Arrows against WASD)\nPress 9 for an Arena (you against three bots)\n\nPress F11 to toggle Full Screen",
+                    self.level.title,
+                );
+                if let Some(message) = &self.message {
+                    menu.push_str(&format!("\n\n{}", message));
+                }
                 let dest_point = ggez::mint::Point2 { x: screen_width / 4.0, y: screen_height / 4.0 };
-                graphics::draw(ctx, &menu_text, (dest_point, Color::WHITE))?;
+                graphics::draw(ctx, &Text::new(menu), (dest_point, Color::WHITE))?;
             },
-            AppState::Playing => {
-                let food_rect = Rect::new(offset_x + self.food.x as f32 * cell_size, offset_y + self.food.y as f32 * cell_size, cell_size, cell_size);
-                let food_mesh = Mesh::new_rectangle(ctx, DrawMode::fill(), food_rect, Color::GREEN)?;
-                graphics::draw(ctx, &food_mesh, DrawParam::default())?;
-                let block_rect = Rect::new(offset_x + self.block.x as f32 * cell_size, offset_y + self.block.y as f32 * cell_size, cell_size, cell_size);
-                let block_color = Color::new(1.0, 0.65, 0.0, 1.0);
-                let block_mesh = Mesh::new_rectangle(ctx, DrawMode::fill(), block_rect, block_color)?;
-                graphics::draw(ctx, &block_mesh, DrawParam::default())?;
-                for segment in &self.snake {
-                    let seg_rect = Rect::new(offset_x + segment.x as f32 * cell_size, offset_y + segment.y as f32 * cell_size, cell_size, cell_size);
-                    let seg_mesh = Mesh::new_rectangle(ctx, DrawMode::fill(), seg_rect, Color::WHITE)?;
-                    graphics::draw(ctx, &seg_mesh, DrawParam::default())?;
+            AppState::Playing | AppState::Competitive => {
+                draw_game(ctx, self.game(), &self.names, cell_size, offset)?;
+            },
+            AppState::Replay => {
+                if let Some(view) = &self.replay {
+                    let playback = &view.playback;
+                    draw_game(ctx, playback.game(), &default_names(playback.game().seats()), cell_size, offset)?;
+                    let mut status = format!(
+                        "Replay tick {}/{}   Speed {}x{}\nSpace pause, Left/Right step, Up/Down speed, Esc menu",
+                        playback.tick(),
+                        playback.len(),
+                        REPLAY_SPEEDS[view.speed],
+                        if view.paused { "   Paused" } else { "" },
+                    );
+                    if let Some(tick) = playback.desync_at() {
+                        status.push_str(&format!("\nOut of sync with the recording since tick {}", tick));
+                    }
+                    graphics::draw(ctx, &Text::new(status), (ggez::mint::Point2 { x: 5.0, y: 25.0 }, Color::WHITE))?;
+                }
+            },
+            AppState::GameOver => {
+                let game = self.game();
+                let result = match game.mode() {
+                    Mode::Single => format!("Game Over! Final Score: {}", game.score()),
+                    Mode::Competitive => {
+                        let ranking = game.ranking();
+                        let winners: Vec<Seat> = ranking.iter().filter(|(place, _)| *place == 1).map(|(_, seat)| *seat).collect();
+                        let headline = match winners[..] {
+                            [Seat::PLAYER] if self.versus_bot => "You win!".to_string(),
+                            [_] if self.versus_bot => "Bot wins!".to_string(),
+                            [seat] => format!("{} wins!", seat_name(&self.names, seat)),
+                            _ => "It's a tie!".to_string(),
+                        };
+                        let mut text = format!("Game Over! {}\n", headline);
+                        for (place, seat) in ranking {
+                            let snake = &game.snakes()[seat.0];
+                            let mut fate = match (snake.death, snake.died_at) {
+                                (Some(death), Some(tick)) => format!("{} on tick {}", fate(death), tick),
+                                _ => "survived".to_string(),
+                            };
+                            // The rules that settled the snake's last tick, if it met others there
+                            let last_tick = snake.died_at.unwrap_or(game.ticks());
+                            let rules: Vec<&str> = game
+                                .rulings()
+                                .iter()
+                                .filter(|ruling| ruling.tick == last_tick && ruling.seats.contains(&seat))
+                                .map(|ruling| ruling.rule.describe())
+                                .collect();
+                            if !rules.is_empty() {
+                                fate.push_str(&format!(" ({})", rules.join(", ")));
+                            }
+                            text.push_str(&format!("\n{}. {}  {} points, {}", place, seat_name(&self.names, seat), snake.score, fate));
+                        }
+                        text.push('\n');
+                        text
+                    },
+                };
+                let mut game_over_text = format!(
+                    "{}\nSeed: {}\nPress Y to Play Again\nPress N to Quit\nPress S to Save the Replay\n\nPress F11 to toggle Full Screen",
+                    result,
+                    game.seed(),
+                );
+                if let Some(message) = &self.message {
+                    game_over_text.push_str(&format!("\n\n{}", message));
                 }
-                let score_text = Text::new(format!("Score: {}", self.score));
-                graphics::draw(ctx, &score_text, (ggez::mint::Point2 { x: 5.0, y: 5.0 }, Color::BLUE))?;
+                let dest_point = ggez::mint::Point2 { x: screen_width / 4.0, y: screen_height / 4.0 };
+                graphics::draw(ctx, &Text::new(game_over_text), (dest_point, Color::RED))?;
             },
-            AppState::Competitive => {
-                let food_rect = Rect::new(offset_x + self.food.x as f32 * cell_size, offset_y + self.food.y as f32 * cell_size, cell_size, cell_size);
-                let food_mesh = Mesh::new_rectangle(ctx, DrawMode::fill(), food_rect, Color::GREEN)?;
-                graphics::draw(ctx, &food_mesh, DrawParam::default())?;
-                let block_rect = Rect::new(offset_x + self.block.x as f32 * cell_size, offset_y + self.block.y as f32 * cell_size, cell_size, cell_size);
-                let block_color = Color::new(1.0, 0.65, 0.0, 1.0);
-                let block_mesh = Mesh::new_rectangle(ctx, DrawMode::fill(), block_rect, block_color)?;
-                graphics::draw(ctx, &block_mesh, DrawParam::default())?;
-                for segment in &self.player_snake {
-                    let seg_rect = Rect::new(offset_x + segment.x as f32 * cell_size, offset_y + segment.y as f32 * cell_size, cell_size, cell_size);
-                    let seg_mesh = Mesh::new_rectangle(ctx, DrawMode::fill(), seg_rect, Color::WHITE)?;
-                    graphics::draw(ctx, &seg_mesh, DrawParam::default())?;
+            AppState::LevelSelect => {
+                if let Some(menu) = &self.level_menu {
+                    let mut text = String::from(if menu.for_editor { "Choose a Level to Edit\n\n" } else { "Choose a Level\n\n" });
+                    for (i, level) in menu.levels.iter().enumerate() {
+                        let marker = if i == menu.selected { ">" } else { " " };
+                        text.push_str(&format!("{} {}  {}x{}\n", marker, level.title, level.width, level.height));
+                    }
+                    text.push_str("\nUp/Down to choose, Enter to pick, Esc to go back");
+                    let dest_point = ggez::mint::Point2 { x: screen_width / 4.0, y: screen_height / 8.0 };
+                    graphics::draw(ctx, &Text::new(text), (dest_point, Color::WHITE))?;
                 }
-                for segment in &self.bot_snake {
-                    let seg_rect = Rect::new(offset_x + segment.x as f32 * cell_size, offset_y + segment.y as f32 * cell_size, cell_size, cell_size);
-                    let seg_mesh = Mesh::new_rectangle(ctx, DrawMode::fill(), seg_rect, Color::YELLOW)?;
-                    graphics::draw(ctx, &seg_mesh, DrawParam::default())?;
+            },
+            AppState::Editor => {
+                if let Some(editor) = &self.editor {
+                    draw_editor(ctx, editor, cell_size, offset)?;
                 }
-                let score_text = Text::new(format!("Player: {}   Bot: {}", self.player_score, self.bot_score));
-                graphics::draw(ctx, &score_text, (ggez::mint::Point2 { x: 5.0, y: 5.0 }, Color::BLUE))?;
             },
-            AppState::GameOver => {
-                let game_over_text = if self.app_state == AppState::GameOver {
-                    if self.player_snake.is_empty() && self.bot_snake.is_empty() {
-                        Text::new("Game Over! It's a tie!\nPress Y to Play Again\nPress N to Quit\n\nPress F11 to toggle Full Screen")
-                    } else if self.player_snake.is_empty() {
-                        Text::new("Game Over! Bot wins!\nPress Y to Play Again\nPress N to Quit\n\nPress F11 to toggle Full Screen")
-                    } else if self.bot_snake.is_empty() {
-                        Text::new("Game Over! You win!\nPress Y to Play Again\nPress N to Quit\n\nPress F11 to toggle Full Screen")
-                    } else {
-                        Text::new(format!("Game Over! Final Score: {}\nPress Y to Play Again\nPress N to Quit\n\nPress F11 to toggle Full Screen", self.score))
+            AppState::Spectating => {
+                if let Some(spectator) = &self.spectator {
+                    let game = spectator.game();
+                    draw_game(ctx, game, spectator.names(), cell_size, offset)?;
+                    let mut status = format!("Spectating, tick {}{}   Esc menu", game.ticks(), if game.is_over() { "   Game over" } else { "" });
+                    if let Some(message) = &self.message {
+                        status.push_str(&format!("\n{}", message));
                     }
-                } else {
-                    Text::new(format!("Game Over! Final Score: {}\nPress Y to Play Again\nPress N to Quit\n\nPress F11 to toggle Full Screen", self.score))
-                };
+                    graphics::draw(ctx, &Text::new(status), (ggez::mint::Point2 { x: 5.0, y: 25.0 }, Color::WHITE))?;
+                }
+            },
+            AppState::PerfectGame => {
+                let game = self.session.game();
+                let mut perfect_text = format!(
+                    "Perfect Game! The snake filled the board\nFinal Score: {} (includes a {} point bonus)\nMoves: {}\nTime: {}\nSeed: {}\nPress Y to Play Again\nPress N to Quit\nPress S to Save the Replay\n\nPress F11 to toggle Full Screen",
+                    game.score(),
+                    PERFECT_GAME_BONUS,
+                    game.ticks(),
+                    format_time(self.play_time),
+                    game.seed(),
+                );
+                if let Some(message) = &self.message {
+                    perfect_text.push_str(&format!("\n\n{}", message));
+                }
                 let dest_point = ggez::mint::Point2 { x: screen_width / 4.0, y: screen_height / 4.0 };
-                graphics::draw(ctx, &game_over_text, (dest_point, Color::RED))?;
+                graphics::draw(ctx, &Text::new(perfect_text), (dest_point, Color::GREEN))?;
             },
         }
         graphics::present(ctx)?;
@@ -412,7 +641,7 @@ impl event::EventHandler for MainState {
             let new_mode = if self.fullscreen {
                 ggez::conf::WindowMode::default().fullscreen_type(ggez::conf::FullscreenType::Desktop)
             } else {
-                ggez::conf::WindowMode::default().dimensions(WINDOW_WIDTH, WINDOW_HEIGHT).resizable(true)
+                ggez::conf::WindowMode::default().dimensions(self.settings.window_width(), self.settings.window_height()).resizable(true)
             };
             graphics::set_mode(ctx, new_mode).unwrap();
             return;
@@ -420,97 +649,360 @@ impl event::EventHandler for MainState {
         match self.app_state {
             AppState::Menu => {
                 match keycode {
-                    KeyCode::Key1 => {
-                        self.auto_play = false;
-                        self.app_state = AppState::Playing;
-                        self.reset();
-                    },
-                    KeyCode::Key2 => {
-                        self.auto_play = true;
-                        self.app_state = AppState::Playing;
-                        self.reset();
-                    },
-                    KeyCode::Key3 => {
-                        self.app_state = AppState::Competitive;
-                        self.reset();
+                    KeyCode::Key1 => self.start(&StrategyKind::Human, None),
+                    KeyCode::Key2 => self.start(&StrategyKind::Pathfinder, None),
+                    KeyCode::Key3 => self.start(&StrategyKind::Human, Some(&StrategyKind::Greedy)),
+                    KeyCode::Key5 => self.start(&StrategyKind::Hamiltonian, None),
+                    KeyCode::Key6 => self.choose_level(false),
+                    KeyCode::Key7 => self.edit(&self.level.clone()),
+                    KeyCode::Key8 => self.start(&StrategyKind::Human, Some(&StrategyKind::Human)),
+                    KeyCode::Key9 => self.start_game(&MENU_ARENA),
+                    KeyCode::Key4 => {
+                        if self.recording.ticks.is_empty() {
+                            self.message = Some(String::from("No game recorded yet"));
+                        } else {
+                            self.watch(self.recording.clone());
+                        }
                     },
                     _ => {},
                 }
             },
-            AppState::Playing => {
-                if !self.auto_play {
-                    match keycode {
-                        KeyCode::Up => {
-                            if self.snake_dir.y != 1 {
-                                self.snake_dir = Point { x: 0, y: -1 };
-                            }
-                        },
-                        KeyCode::Down => {
-                            if self.snake_dir.y != -1 {
-                                self.snake_dir = Point { x: 0, y: 1 };
-                            }
-                        },
-                        KeyCode::Left => {
-                            if self.snake_dir.x != 1 {
-                                self.snake_dir = Point { x: -1, y: 0 };
-                            }
-                        },
-                        KeyCode::Right => {
-                            if self.snake_dir.x != -1 {
-                                self.snake_dir = Point { x: 1, y: 0 };
-                            }
-                        },
-                        _ => {},
-                    }
+            AppState::Playing | AppState::Competitive => {
+                // Turns are handed to the human strategy on the next tick
+                let dir = match keycode {
+                    KeyCode::Up => Some(Direction::Up),
+                    KeyCode::Down => Some(Direction::Down),
+                    KeyCode::Left => Some(Direction::Left),
+                    KeyCode::Right => Some(Direction::Right),
+                    _ => None,
+                };
+                if dir.is_some() {
+                    self.inputs.player = dir;
+                }
+                // The yellow snake is only steered by these when a second person plays it
+                let second = match keycode {
+                    KeyCode::W => Some(Direction::Up),
+                    KeyCode::S => Some(Direction::Down),
+                    KeyCode::A => Some(Direction::Left),
+                    KeyCode::D => Some(Direction::Right),
+                    _ => None,
+                };
+                if second.is_some() {
+                    self.inputs.bot = second;
+                }
+                // A test play of the level being edited can be cut short
+                if keycode == KeyCode::Escape && self.editor.is_some() {
+                    self.leave_game();
                 }
             },
-            AppState::Competitive => {
+            AppState::Replay => {
+                let Some(view) = self.replay.as_mut() else {
+                    return;
+                };
                 match keycode {
-                    KeyCode::Up => {
-                        if self.player_snake_dir.y != 1 {
-                            self.player_snake_dir = Point { x: 0, y: -1 };
-                        }
-                    },
-                    KeyCode::Down => {
-                        if self.player_snake_dir.y != -1 {
-                            self.player_snake_dir = Point { x: 0, y: 1 };
-                        }
+                    KeyCode::Space => view.paused = !view.paused,
+                    KeyCode::Right => {
+                        view.paused = true;
+                        view.playback.step_forward();
                     },
                     KeyCode::Left => {
-                        if self.player_snake_dir.x != 1 {
-                            self.player_snake_dir = Point { x: -1, y: 0 };
-                        }
+                        view.paused = true;
+                        view.playback.step_back();
                     },
-                    KeyCode::Right => {
-                        if self.player_snake_dir.x != -1 {
-                            self.player_snake_dir = Point { x: 1, y: 0 };
-                        }
+                    KeyCode::Up => view.speed = (view.speed + 1).min(REPLAY_SPEEDS.len() - 1),
+                    KeyCode::Down => view.speed = view.speed.saturating_sub(1),
+                    KeyCode::Escape => {
+                        self.replay = None;
+                        self.app_state = AppState::Menu;
                     },
                     _ => {},
                 }
             },
-            AppState::GameOver => {
+            AppState::LevelSelect => {
+                let Some(menu) = self.level_menu.as_mut() else {
+                    return;
+                };
                 match keycode {
-                    KeyCode::Y => {
-                        self.reset();
-                        self.app_state = AppState::Menu;
+                    KeyCode::Up => menu.selected = menu.selected.saturating_sub(1),
+                    KeyCode::Down => menu.selected = (menu.selected + 1).min(menu.levels.len() - 1),
+                    KeyCode::Return => {
+                        let level = menu.levels[menu.selected].clone();
+                        let for_editor = menu.for_editor;
+                        self.level_menu = None;
+                        if for_editor {
+                            self.edit(&level);
+                        } else {
+                            self.level = level;
+                            self.app_state = AppState::Menu;
+                        }
                     },
+                    KeyCode::Escape => {
+                        self.app_state = if menu.for_editor { AppState::Editor } else { AppState::Menu };
+                        self.level_menu = None;
+                    },
+                    _ => {},
+                }
+            },
+            AppState::GameOver | AppState::PerfectGame => {
+                match keycode {
+                    KeyCode::Y => self.leave_game(),
                     KeyCode::N => {
                         ggez::event::quit(ctx);
                     },
+                    KeyCode::S => self.save_replay(),
                     _ => {},
                 }
             },
+            AppState::Spectating => {
+                if keycode == KeyCode::Escape {
+                    self.spectator = None;
+                    self.message = None;
+                    self.app_state = AppState::Menu;
+                }
+            },
+            AppState::Editor => {
+                let Some(editor) = self.editor.as_mut() else {
+                    return;
+                };
+                match editor.key(keycode) {
+                    Action::None => {},
+                    Action::Load => self.choose_level(true),
+                    Action::Test { versus } => self.test_level(versus),
+                    Action::Quit => {
+                        self.editor = None;
+                        self.app_state = AppState::Menu;
+                    },
+                }
+            },
+        }
+    }
+
+    // The D-pad steers the yellow snake of a competitive game, the only snake of a single one
+    fn gamepad_button_down_event(&mut self, _ctx: &mut Context, btn: Button, _id: GamepadId) {
+        let dir = match btn {
+            Button::DPadUp => Direction::Up,
+            Button::DPadDown => Direction::Down,
+            Button::DPadLeft => Direction::Left,
+            Button::DPadRight => Direction::Right,
+            _ => return,
+        };
+        match self.app_state {
+            // Online the local snake is always read from the player's input, whichever seat it has
+            AppState::Competitive if self.online.is_some() => self.inputs.player = Some(dir),
+            AppState::Competitive => self.inputs.bot = Some(dir),
+            AppState::Playing => self.inputs.player = Some(dir),
+            _ => {},
+        }
+    }
+
+    fn text_input_event(&mut self, _ctx: &mut Context, character: char) {
+        if let (AppState::Editor, Some(editor)) = (&self.app_state, self.editor.as_mut()) {
+            editor.type_char(character);
         }
     }
+
+    fn mouse_button_down_event(&mut self, ctx: &mut Context, button: MouseButton, x: f32, y: f32) {
+        if self.app_state != AppState::Editor {
+            return;
+        }
+        if let (Some(cell), Some(editor)) = (self.cell_at(ctx, x, y), self.editor.as_mut()) {
+            editor.press(cell, button);
+        }
+    }
+
+    fn mouse_motion_event(&mut self, ctx: &mut Context, x: f32, y: f32, _dx: f32, _dy: f32) {
+        if self.app_state != AppState::Editor {
+            return;
+        }
+        if let (Some(cell), Some(editor)) = (self.cell_at(ctx, x, y), self.editor.as_mut()) {
+            editor.drag(cell);
+        }
+    }
+
+    fn mouse_button_up_event(&mut self, _ctx: &mut Context, _button: MouseButton, _x: f32, _y: f32) {
+        if let Some(editor) = self.editor.as_mut() {
+            editor.release();
+        }
+    }
+}
+
+// Minutes and seconds, like 3:07.4
+fn format_time(seconds: f32) -> String {
+    format!("{}:{:04.1}", (seconds / 60.0) as u32, seconds % 60.0)
+}
+
+// A human player needs the window
+fn check_headless(kind: &StrategyKind, headless: bool) {
+    if headless && *kind == StrategyKind::Human {
+        fail("a human player needs the window, pick a bot with --player or leave out --headless".to_string());
+    }
+}
+
+fn online_strategy(kind: &StrategyKind, settings: &Settings, level: &Level) -> Box<dyn Strategy> {
+    kind.build(settings, level).unwrap_or_else(|e| fail(e.to_string()))
+}
+
+fn fail(message: String) -> ! {
+    eprintln!("rusty_snake: {}", message);
+    std::process::exit(2);
 }
 
 pub fn main() -> GameResult {
+    let cli = Cli::parse();
+    let settings = cli.options.settings().unwrap_or_else(|e| fail(e));
+    let level = cli.options.level().unwrap_or_else(|e| fail(e));
+    // The level decides the size of the board
+    let board_settings = level.as_ref().map_or(settings, |level| level.settings(&settings));
+    let command = cli.command.unwrap_or(Command::Menu);
+    // Simulations never open a window
+    if let Command::Simulate { games, player, bot, max_ticks, threads } = command {
+        let simulation = Simulation {
+            settings: board_settings,
+            level: level.map(Arc::new),
+            player,
+            bot,
+            games,
+            seed: cli.options.seed.unwrap_or_else(rand::random),
+            max_ticks,
+            threads: threads.unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |n| n.get())),
+        };
+        let report = simulation.run().unwrap_or_else(|e| fail(e.to_string()));
+        print!("{}", report);
+        return Ok(());
+    }
+    if let Command::Tournament { strategies, games, swiss, max_ticks, threads, csv, json } = command {
+        let tournament = Tournament {
+            settings: board_settings,
+            level: level.map(Arc::new),
+            entrants: strategies,
+            format: swiss.map_or(Format::RoundRobin, |rounds| Format::Swiss { rounds }),
+            games,
+            seed: cli.options.seed.unwrap_or_else(rand::random),
+            max_ticks,
+            threads: threads.unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |n| n.get())),
+        };
+        let report = tournament.run().unwrap_or_else(|e| fail(e.to_string()));
+        print!("{}", report);
+        if let Some(path) = csv {
+            report.save_csv(&path).unwrap_or_else(|e| fail(format!("could not write {}: {}", path.display(), e)));
+        }
+        if let Some(path) = json {
+            report.save_json(&path).unwrap_or_else(|e| fail(format!("could not write {}: {}", path.display(), e)));
+        }
+        return Ok(());
+    }
+    let broadcast = cli.options.broadcast.map(|port| {
+        let broadcast = Broadcast::start(port).unwrap_or_else(|e| fail(format!("could not broadcast on port {}: {}", port, e)));
+        eprintln!("Spectators can watch on port {}", broadcast.port());
+        broadcast
+    });
+    // Networked games connect before the window opens, the guest plays on the host's board
+    let online = match &command {
+        Command::Host { port, player, headless } => {
+            check_headless(player, *headless);
+            let level = Arc::new(level.clone().unwrap_or_else(|| Level::open(settings.grid_width, settings.grid_height)));
+            let strategy = online_strategy(player, &board_settings, &level);
+            let seed = cli.options.seed.unwrap_or_else(rand::random);
+            eprintln!("Waiting for a player to join on port {}", port);
+            let peer = Peer::host(*port, &settings, level, seed).unwrap_or_else(|e| fail(e.to_string()));
+            Some((peer, strategy, *headless))
+        },
+        Command::Join { address, player, headless } => {
+            check_headless(player, *headless);
+            let address = if address.contains(':') { address.clone() } else { format!("{}:{}", address, DEFAULT_PORT) };
+            let peer = Peer::join(address.as_str(), &settings).unwrap_or_else(|e| fail(e.to_string()));
+            // Built only now, some strategies depend on the host's board
+            let strategy = online_strategy(player, peer.settings(), peer.game().level());
+            Some((peer, strategy, *headless))
+        },
+        _ => None,
+    };
+    if let Some((mut peer, mut strategy, true)) = online {
+        if let Some(broadcast) = &broadcast {
+            broadcast.start_game(peer.game(), &ONLINE_NAMES.map(String::from));
+        }
+        let on_tick = |game: &Game| {
+            if let Some(broadcast) = &broadcast {
+                broadcast.record(game);
+            }
+        };
+        peer.play(strategy.as_mut(), on_tick).unwrap_or_else(|e| fail(e.to_string()));
+        let game = peer.game();
+        let [host, guest] = ONLINE_NAMES;
+        println!("{}: {}   {}: {}", host, game.player_score(), guest, game.bot_score());
+        println!("Ticks: {}   State hash: {:016x}", game.ticks(), game.state_hash());
+        return Ok(());
+    }
+    // Spectators likewise wait for the first game to size the window
+    let spectator = match &command {
+        Command::Spectate { address } => {
+            let address = if address.contains(':') { address.clone() } else { format!("{}:{}", address, DEFAULT_SPECTATE_PORT) };
+            eprintln!("Waiting for a game on {}", address);
+            Some(Spectator::connect(address.as_str()).unwrap_or_else(|e| fail(e.to_string())))
+        },
+        _ => None,
+    };
+    let window_settings = match (&online, &spectator) {
+        (Some((peer, _, _)), _) => *peer.settings(),
+        (_, Some(spectator)) => Settings { grid_width: spectator.game().width(), grid_height: spectator.game().height(), ..settings },
+        _ => board_settings,
+    };
+
+    // Check everything before opening the window so bad input fails fast
+    let replay = match &command {
+        Command::Replay { file } => Some(Replay::load(file).unwrap_or_else(|e| fail(e.to_string()))),
+        _ => None,
+    };
+    let snakes = match &command {
+        Command::Play { player } => vec![player.clone()],
+        Command::Selfplay { bot } => vec![bot.clone()],
+        Command::Versus { player, bot } => vec![player.clone(), bot.clone()],
+        Command::Arena { snakes } => snakes.clone(),
+        Command::Menu
+        | Command::Replay { .. }
+        | Command::Simulate { .. }
+        | Command::Tournament { .. }
+        | Command::Host { .. }
+        | Command::Join { .. }
+        | Command::Spectate { .. } => vec![],
+    };
+    // The keyboard steers only the first two seats
+    if snakes.iter().skip(2).any(|kind| *kind == StrategyKind::Human) {
+        fail("only the first two snakes can be human, the arrow keys steer the first and WASD the second".to_string());
+    }
+    let board_level = level.clone().unwrap_or_else(|| Level::open(settings.grid_width, settings.grid_height));
+    board_level.room_for(snakes.len()).unwrap_or_else(|e| fail(e));
+    for kind in &snakes {
+        if let Err(e) = kind.build(&board_settings, &board_level) {
+            fail(e.to_string());
+        }
+    }
+
     let (mut ctx, event_loop) = ContextBuilder::new("Self-Playing Snake", "Author")
         .window_setup(ggez::conf::WindowSetup::default().title("Self-Playing Snake"))
-        .window_mode(ggez::conf::WindowMode::default().dimensions(WINDOW_WIDTH, WINDOW_HEIGHT).resizable(true))
+        .window_mode(ggez::conf::WindowMode::default().dimensions(window_settings.window_width(), window_settings.window_height()).resizable(true))
         .build()?;
 
-    let state = MainState::new(&mut ctx)?;
+    let mut state = MainState::new(&mut ctx, settings, level, cli.options.seed)?;
+    state.broadcast = broadcast;
+    if let Some((peer, strategy, _)) = online {
+        state.go_online(peer, strategy);
+    }
+    if let Some(spectator) = spectator {
+        state.spectate(spectator);
+    }
+    match command {
+        Command::Menu | Command::Host { .. } | Command::Join { .. } | Command::Spectate { .. } => {},
+        Command::Replay { .. } => {
+            if let Some(replay) = replay {
+                state.watch(replay);
+            }
+        },
+        _ => {
+            if !snakes.is_empty() {
+                state.start_game(&snakes);
+            }
+        },
+    }
     event::run(ctx, event_loop, state)
 }
@@ -0,0 +1,358 @@
+// Competitive games between two machines.
+// The host listens for a single player to join and tells it the seed, the level and the settings
+// of the game. From then on both sides run the same rules in lockstep: every tick each side sends
+// the move of its own snake and waits for the move of the other one before advancing. The host
+// steers the player snake and the guest the bot snake.
+//
+// Messages are lines of JSON:
+//
+//     {"type":"hello","protocol":2,"seed":42,"wrap":false,"head_on":"longer-wins","food_tie":"nobody","move_interval":0.1,"level":"box","map":"..."}
+//     {"type":"move","tick":0,"dir":"up"}
+//     {"type":"hash","tick":10,"hash":1234567890}
+//
+// Every HASH_INTERVAL ticks both sides send a hash of their game, so a desync is noticed close to
+// where it happened instead of when the boards have drifted far apart.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{Shutdown, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use crate::game::{Direction, Game, Mode, Moves, Seat, StepOutcome};
+use crate::level::{Level, LevelError};
+use crate::settings::{FoodTie, HeadOn, Settings};
+use crate::strategy::Strategy;
+
+pub const DEFAULT_PORT: u16 = 7878;
+
+// Bumped whenever the messages or the rules change in a way older builds would not follow
+const PROTOCOL: u32 = 2;
+
+// Ticks between two state hashes
+pub const HASH_INTERVAL: u64 = 10;
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum Message {
+    Hello { protocol: u32, seed: u64, wrap: bool, head_on: HeadOn, food_tie: FoodTie, move_interval: f32, level: String, map: String },
+    Move { tick: u64, dir: Option<Direction> },
+    Hash { tick: u64, hash: u64 },
+}
+
+#[derive(Debug)]
+pub enum NetError {
+    Io(io::Error),
+    // The other side sent something this build does not understand
+    Protocol(String),
+    Level(LevelError),
+    Closed,
+    // The two games differ since this tick
+    Desync(u64),
+}
+
+impl fmt::Display for NetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NetError::Io(e) => write!(f, "network error: {}", e),
+            NetError::Protocol(message) => write!(f, "protocol error: {}", message),
+            NetError::Level(e) => write!(f, "the host's level is not playable: {}", e),
+            NetError::Closed => write!(f, "the other side left the game"),
+            NetError::Desync(tick) => write!(f, "the games went out of sync by tick {}", tick),
+        }
+    }
+}
+
+impl std::error::Error for NetError {}
+
+impl From<io::Error> for NetError {
+    fn from(e: io::Error) -> NetError {
+        NetError::Io(e)
+    }
+}
+
+// One side of a networked game
+pub struct Peer {
+    seat: Seat,
+    settings: Settings,
+    game: Game,
+    stream: TcpStream,
+    // Messages read from the other side by a background thread
+    incoming: Receiver<Result<Message, NetError>>,
+    // Moves for the coming tick, the own one once it has been sent and the other side's once it is in
+    sent: Option<Option<Direction>>,
+    received: Option<Option<Direction>>,
+    // Hashes by tick, kept until the other side's hash for the same tick is in
+    own_hashes: HashMap<u64, u64>,
+    their_hashes: HashMap<u64, u64>,
+}
+
+impl Peer {
+    // Waits on `port` for someone to join, then starts a game with `seed` on `level`
+    pub fn host(port: u16, settings: &Settings, level: Arc<Level>, seed: u64) -> Result<Peer, NetError> {
+        let listener = TcpListener::bind(("0.0.0.0", port))?;
+        let (stream, _) = listener.accept()?;
+        let reader = BufReader::new(stream.try_clone()?);
+        let mut peer = Self::connect(stream, reader, Seat::PLAYER, level.settings(settings), level.clone(), seed)?;
+        peer.send(&Message::Hello {
+            protocol: PROTOCOL,
+            seed,
+            wrap: settings.wrap,
+            head_on: settings.head_on,
+            food_tie: settings.food_tie,
+            move_interval: settings.move_interval,
+            level: level.name.clone(),
+            map: level.to_toml(),
+        })?;
+        Ok(peer)
+    }
+
+    // Joins the game hosted at `address`, the host decides everything but the look of the window
+    pub fn join(address: impl ToSocketAddrs, settings: &Settings) -> Result<Peer, NetError> {
+        let stream = TcpStream::connect(address)?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(NetError::Closed);
+        }
+        let Message::Hello { protocol, seed, wrap, head_on, food_tie, move_interval, level, map } = parse(&line)? else {
+            return Err(NetError::Protocol(format!("expected a hello, got {}", line.trim())));
+        };
+        if protocol != PROTOCOL {
+            return Err(NetError::Protocol(format!("the host speaks protocol {}, this build {}", protocol, PROTOCOL)));
+        }
+        let level = Arc::new(Level::parse(&level, &map).map_err(NetError::Level)?);
+        let settings = Settings { wrap, head_on, food_tie, move_interval, ..level.settings(settings) };
+        // The reader keeps whatever it read past the hello
+        Self::connect(stream, reader, Seat::BOT, settings, level, seed)
+    }
+
+    fn connect(stream: TcpStream, reader: BufReader<TcpStream>, seat: Seat, settings: Settings, level: Arc<Level>, seed: u64) -> Result<Peer, NetError> {
+        // Moves are tiny and every tick waits on them
+        stream.set_nodelay(true)?;
+        let (sender, incoming) = mpsc::channel();
+        thread::spawn(move || {
+            for line in reader.lines() {
+                let message = line.map_err(NetError::from).and_then(|line| parse(&line));
+                let failed = message.is_err();
+                if sender.send(message).is_err() || failed {
+                    break;
+                }
+            }
+        });
+        let game = Game::on_level(Mode::Competitive, seed, &settings, level);
+        Ok(Peer {
+            seat,
+            settings,
+            game,
+            stream,
+            incoming,
+            sent: None,
+            received: None,
+            own_hashes: HashMap::new(),
+            their_hashes: HashMap::new(),
+        })
+    }
+
+    pub fn game(&self) -> &Game {
+        &self.game
+    }
+
+    // The snake steered from this side
+    pub fn seat(&self) -> Seat {
+        self.seat
+    }
+
+    // Settings of the game, the same on both sides but for the look of the window
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+
+    // True when the own move for the coming tick is out and the other side's is missing
+    pub fn is_waiting(&self) -> bool {
+        self.sent.is_some() && self.received.is_none()
+    }
+
+    fn send(&mut self, message: &Message) -> Result<(), NetError> {
+        let line = serde_json::to_string(message).expect("messages always serialize");
+        writeln!(self.stream, "{}", line)?;
+        self.stream.flush()?;
+        Ok(())
+    }
+
+    // Sends the move of the own snake for the coming tick, later calls in the same tick are ignored
+    pub fn send_move(&mut self, dir: Option<Direction>) -> Result<(), NetError> {
+        if self.sent.is_none() && !self.game.is_over() {
+            self.send(&Message::Move { tick: self.game.ticks(), dir })?;
+            self.sent = Some(dir);
+        }
+        Ok(())
+    }
+
+    // Advances the game once the other side's move is in, `None` while it is still on its way
+    pub fn poll(&mut self) -> Result<Option<StepOutcome>, NetError> {
+        loop {
+            if let Some(outcome) = self.advance()? {
+                return Ok(Some(outcome));
+            }
+            match self.incoming.try_recv() {
+                Ok(message) => self.receive(message?)?,
+                Err(TryRecvError::Empty) => return Ok(None),
+                Err(TryRecvError::Disconnected) => return Err(NetError::Closed),
+            }
+        }
+    }
+
+    // Like poll, but blocks until the game advanced
+    pub fn wait(&mut self) -> Result<StepOutcome, NetError> {
+        loop {
+            if let Some(outcome) = self.advance()? {
+                return Ok(outcome);
+            }
+            let message = self.incoming.recv().map_err(|_| NetError::Closed)?;
+            self.receive(message?)?;
+        }
+    }
+
+    // The other side can be at most one move ahead, it waits on this side's move for the next tick
+    fn receive(&mut self, message: Message) -> Result<(), NetError> {
+        match message {
+            Message::Hello { .. } => Err(NetError::Protocol("hello in the middle of a game".to_string())),
+            Message::Hash { tick, hash } => {
+                self.their_hashes.insert(tick, hash);
+                self.check_hash(tick)
+            },
+            Message::Move { tick, dir } => {
+                if tick != self.game.ticks() || self.received.is_some() {
+                    return Err(NetError::Protocol(format!("move for tick {} during tick {}", tick, self.game.ticks())));
+                }
+                self.received = Some(dir);
+                Ok(())
+            },
+        }
+    }
+
+    // Plays the tick once both moves are in
+    fn advance(&mut self) -> Result<Option<StepOutcome>, NetError> {
+        let (Some(own), Some(theirs)) = (self.sent, self.received) else {
+            return Ok(None);
+        };
+        self.sent = None;
+        self.received = None;
+        let moves = match self.seat {
+            Seat::PLAYER => Moves::pair(own, theirs),
+            _ => Moves::pair(theirs, own),
+        };
+        let outcome = self.game.advance(moves);
+        let tick = self.game.ticks();
+        if tick.is_multiple_of(HASH_INTERVAL) || outcome == StepOutcome::GameOver {
+            let hash = self.game.state_hash();
+            self.send(&Message::Hash { tick, hash })?;
+            self.own_hashes.insert(tick, hash);
+            self.check_hash(tick)?;
+        }
+        Ok(Some(outcome))
+    }
+
+    fn check_hash(&mut self, tick: u64) -> Result<(), NetError> {
+        if let (Some(own), Some(theirs)) = (self.own_hashes.get(&tick), self.their_hashes.get(&tick)) {
+            if own != theirs {
+                return Err(NetError::Desync(tick));
+            }
+            self.own_hashes.remove(&tick);
+            self.their_hashes.remove(&tick);
+        }
+        Ok(())
+    }
+
+    // Plays the whole game with `strategy` steering the own snake, as fast as the other side allows.
+    // `on_tick` sees the game after every tick.
+    pub fn play(&mut self, strategy: &mut dyn Strategy, mut on_tick: impl FnMut(&Game)) -> Result<(), NetError> {
+        while !self.game.is_over() {
+            let dir = strategy.choose(&self.game.view(self.seat, None));
+            self.send_move(dir)?;
+            self.wait()?;
+            on_tick(&self.game);
+        }
+        strategy.finish(&self.game.view(self.seat, None));
+        // The last hash may still be on its way
+        while !self.their_hashes.is_empty() || !self.own_hashes.is_empty() {
+            match self.incoming.recv() {
+                Ok(message) => self.receive(message?)?,
+                Err(_) => break,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Peer {
+    fn drop(&mut self) {
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+}
+
+fn parse(line: &str) -> Result<Message, NetError> {
+    serde_json::from_str(line.trim()).map_err(|e| NetError::Protocol(format!("{} in {:?}", e, line.trim())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use crate::strategy::Greedy;
+
+    // A host and a guest connected over the loopback
+    fn pair(seed: u64) -> (Peer, Peer) {
+        let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let settings = Settings::default();
+        let level = Arc::new(Level::open(settings.grid_width, settings.grid_height));
+        let host = thread::spawn(move || Peer::host(port, &Settings::default(), level, seed).unwrap());
+        let guest = loop {
+            match Peer::join(("127.0.0.1", port), &settings) {
+                Ok(guest) => break guest,
+                Err(NetError::Io(_)) => thread::sleep(Duration::from_millis(10)),
+                Err(e) => panic!("{}", e),
+            }
+        };
+        (host.join().unwrap(), guest)
+    }
+
+    // Plays up to `ticks` ticks on a thread of its own, giving the state hash after each one
+    fn play(mut peer: Peer, ticks: usize) -> thread::JoinHandle<Result<Vec<u64>, NetError>> {
+        thread::spawn(move || {
+            let mut hashes = vec![];
+            while hashes.len() < ticks && !peer.game().is_over() {
+                let dir = Greedy.choose(&peer.game().view(peer.seat(), None));
+                peer.send_move(dir)?;
+                peer.wait()?;
+                hashes.push(peer.game().state_hash());
+            }
+            Ok(hashes)
+        })
+    }
+
+    #[test]
+    fn both_sides_play_the_same_game() {
+        let (host, guest) = pair(42);
+        // Stops between two hashes, a side that stopped would not read the other one's hash
+        let (host, guest) = (play(host, 55), play(guest, 55));
+        let host = host.join().unwrap().unwrap();
+        let guest = guest.join().unwrap().unwrap();
+        assert!(host.len() > 2 * HASH_INTERVAL as usize);
+        assert_eq!(host, guest);
+    }
+
+    #[test]
+    fn a_desync_is_reported() {
+        let (host, mut guest) = pair(42);
+        guest.game = Game::on_level(Mode::Competitive, 43, &guest.settings, guest.game.level().clone());
+        let (host, guest) = (play(host, 60), play(guest, 60));
+        // Whichever side notices first leaves, the other one may only see the connection go
+        let results = [host.join().unwrap(), guest.join().unwrap()];
+        assert!(results.iter().all(Result::is_err));
+        assert!(results.iter().any(|result| matches!(result, Err(NetError::Desync(HASH_INTERVAL)))));
+    }
+}
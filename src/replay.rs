@@ -0,0 +1,406 @@
+// Replays for Rusty Snake.
+// A replay is the seed plus every move made, which is enough to rebuild each tick of a game.
+// Spawns are stored too, so playback can tell when the rules have drifted from the recording.
+//
+// The file is plain text:
+//
+//     rusty_snake replay 1
+//     mode competitive
+//     grid 50x40 wrap
+//     level rooms
+//     | title = "Rooms"
+//     | map = """
+//     | ...
+//     | """
+//     rules longer-wins nobody
+//     seed 42
+//     RU F12,3 B4,5
+//     R-
+//
+// `wrap` after the grid size marks a board whose edges wrap around. The level line is left out
+// for games on an open board, otherwise it is followed by the level file with `| ` in front of
+// each line, so a replay plays back without the level at hand. Older replays give only the name,
+// the level is looked up by it when they are read.
+// Competitive games give the head-on and the food tie rule, older replays without the line are
+// read with the default rules.
+// One line per tick holds the player and bot move (`U`, `D`, `L`, `R`, or `-` for none)
+// followed by the food (`F`) and block (`B`) spawned during that tick.
+// A competitive game between more than two snakes gives their number after the mode, as in
+// `mode competitive 4`, and has a move for every snake on each tick line.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use crate::game::{Direction, Game, MAX_SNAKES, Mode, Moves, Point, Seat, Spawn};
+use crate::level::Level;
+use crate::settings::{FoodTie, HeadOn, Settings};
+
+const HEADER: &str = "rusty_snake replay 1";
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Tick {
+    pub moves: Moves,
+    pub spawns: Vec<Spawn>,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Replay {
+    pub mode: Mode,
+    // Snakes in the game, one for single mode
+    pub seats: usize,
+    pub width: i32,
+    pub height: i32,
+    pub wrap: bool,
+    // None for an open board
+    pub level: Option<Arc<Level>>,
+    pub seed: u64,
+    pub head_on: HeadOn,
+    pub food_tie: FoodTie,
+    pub ticks: Vec<Tick>,
+}
+
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(io::Error),
+    Parse { line: usize, message: String },
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReplayError::Io(e) => write!(f, "could not read replay: {}", e),
+            ReplayError::Parse { line, message } => write!(f, "bad replay at line {}: {}", line, message),
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+impl From<io::Error> for ReplayError {
+    fn from(e: io::Error) -> ReplayError {
+        ReplayError::Io(e)
+    }
+}
+
+impl Replay {
+    // Starts an empty recording for the given game
+    pub fn new(game: &Game) -> Replay {
+        Replay {
+            mode: game.mode(),
+            seats: game.seats(),
+            width: game.width(),
+            height: game.height(),
+            wrap: game.wraps(),
+            level: Some(game.level().clone()).filter(|level| !level.is_open()),
+            seed: game.seed(),
+            head_on: game.head_on(),
+            food_tie: game.food_tie(),
+            ticks: vec![],
+        }
+    }
+
+    // Appends the tick the game has just played
+    pub fn record(&mut self, game: &Game) {
+        self.ticks.push(Tick {
+            moves: game.last_moves().clone(),
+            spawns: game.last_spawns().to_vec(),
+        });
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, self.to_string())
+    }
+
+    pub fn load(path: &Path) -> Result<Replay, ReplayError> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn parse(text: &str) -> Result<Replay, ReplayError> {
+        let mut lines = text.lines().enumerate().map(|(i, line)| (i + 1, line.trim()));
+        let error = |line: usize, message: &str| ReplayError::Parse { line, message: message.to_string() };
+
+        match lines.next() {
+            Some((_, HEADER)) => {},
+            Some((line, _)) => return Err(error(line, "not a rusty_snake replay")),
+            None => return Err(error(1, "empty file")),
+        }
+        let (line, mode) = lines.next().ok_or(error(2, "missing mode"))?;
+        let (mode, seats) = match mode.strip_prefix("mode ").map(|mode| mode.split_once(' ').unwrap_or((mode, ""))) {
+            // Older replays tell apart who steered a single snake
+            Some(("single" | "manual" | "selfplay", "")) => (Mode::Single, 1),
+            Some(("competitive", "")) => (Mode::Competitive, 2),
+            Some(("competitive", seats)) => match seats.parse() {
+                Ok(seats) if (2..=MAX_SNAKES).contains(&seats) => (Mode::Competitive, seats),
+                _ => return Err(error(line, &format!("a competitive game has 2 to {} snakes", MAX_SNAKES))),
+            },
+            _ => return Err(error(line, "expected `mode single|competitive` or `mode competitive <snakes>`")),
+        };
+        let (line, grid) = lines.next().ok_or(error(3, "missing grid"))?;
+        let (grid, wrap) = match grid.strip_suffix(" wrap") {
+            Some(grid) => (grid, true),
+            None => (grid, false),
+        };
+        let (width, height) = grid
+            .strip_prefix("grid ")
+            .and_then(|s| s.split_once('x'))
+            .and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)))
+            .ok_or(error(line, "expected `grid <width>x<height>` or `grid <width>x<height> wrap`"))?;
+        let settings = Settings { grid_width: width, grid_height: height, ..Settings::default() };
+        settings.validate().map_err(|e| error(line, &e.to_string()))?;
+        let (mut line, mut seed) = lines.next().ok_or(error(4, "missing seed"))?;
+        let mut level = None;
+        if let Some(name) = seed.strip_prefix("level ") {
+            let level_line = line;
+            let mut map = String::new();
+            (line, seed) = lines.next().ok_or(error(line + 1, "missing seed"))?;
+            while let Some(text) = seed.strip_prefix('|') {
+                map.push_str(text.strip_prefix(' ').unwrap_or(text));
+                map.push('\n');
+                (line, seed) = lines.next().ok_or(error(line + 1, "missing seed"))?;
+            }
+            let found = if map.is_empty() { Level::find(name) } else { Level::parse(name, &map) };
+            let found = found.map_err(|e| error(level_line, &e.to_string()))?;
+            if (found.width, found.height) != (width, height) {
+                return Err(error(level_line, &format!("level {} is {}x{}, not {}x{}", name, found.width, found.height, width, height)));
+            }
+            level = Some(Arc::new(found));
+        }
+        let (mut head_on, mut food_tie) = (HeadOn::default(), FoodTie::default());
+        if let Some(rules) = seed.strip_prefix("rules ") {
+            (head_on, food_tie) = rules
+                .split_once(' ')
+                .and_then(|(head_on, food_tie)| Some((head_on.parse().ok()?, food_tie.parse().ok()?)))
+                .ok_or(error(line, "expected `rules <head-on rule> <food tie rule>`"))?;
+            (line, seed) = lines.next().ok_or(error(line + 1, "missing seed"))?;
+        }
+        let seed = seed
+            .strip_prefix("seed ")
+            .and_then(|s| s.parse().ok())
+            .ok_or(error(line, "expected `seed <number>`"))?;
+
+        let mut ticks = vec![];
+        for (line, text) in lines.filter(|(_, text)| !text.is_empty()) {
+            let mut parts = text.split_whitespace();
+            let moves: Vec<char> = parts.next().unwrap_or("").chars().collect();
+            if moves.len() != move_chars(seats) {
+                return Err(error(line, &format!("expected {} move characters", move_chars(seats))));
+            }
+            let moves = Moves(moves.into_iter().map(parse_move).collect::<Option<_>>().ok_or(error(line, "unknown move"))?);
+            let mut spawns = vec![];
+            for part in parts {
+                spawns.push(parse_spawn(part).ok_or(error(line, "bad spawn"))?);
+            }
+            ticks.push(Tick { moves, spawns });
+        }
+        Ok(Replay { mode, seats, width, height, wrap, level, seed, head_on, food_tie, ticks })
+    }
+}
+
+impl fmt::Display for Replay {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mode = match self.mode {
+            Mode::Single => "single",
+            Mode::Competitive => "competitive",
+        };
+        writeln!(f, "{}", HEADER)?;
+        if self.seats > 2 {
+            writeln!(f, "mode {} {}", mode, self.seats)?;
+        } else {
+            writeln!(f, "mode {}", mode)?;
+        }
+        writeln!(f, "grid {}x{}{}", self.width, self.height, if self.wrap { " wrap" } else { "" })?;
+        if let Some(level) = &self.level {
+            writeln!(f, "level {}", level.name)?;
+            for text in level.to_toml().lines() {
+                writeln!(f, "| {}", text)?;
+            }
+        }
+        if self.mode == Mode::Competitive {
+            writeln!(f, "rules {} {}", self.head_on.name(), self.food_tie.name())?;
+        }
+        writeln!(f, "seed {}", self.seed)?;
+        for tick in &self.ticks {
+            for seat in 0..move_chars(self.seats) {
+                write!(f, "{}", move_char(tick.moves.get(Seat(seat))))?;
+            }
+            for spawn in &tick.spawns {
+                match spawn {
+                    Spawn::Food(p) => write!(f, " F{},{}", p.x, p.y)?,
+                    Spawn::Block(p) => write!(f, " B{},{}", p.x, p.y)?,
+                }
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+// Tick lines hold a move for the player and the bot seat even in single mode
+fn move_chars(seats: usize) -> usize {
+    seats.max(2)
+}
+
+fn move_char(dir: Option<Direction>) -> char {
+    match dir {
+        Some(Direction::Up) => 'U',
+        Some(Direction::Down) => 'D',
+        Some(Direction::Left) => 'L',
+        Some(Direction::Right) => 'R',
+        None => '-',
+    }
+}
+
+fn parse_move(c: char) -> Option<Option<Direction>> {
+    match c {
+        'U' => Some(Some(Direction::Up)),
+        'D' => Some(Some(Direction::Down)),
+        'L' => Some(Some(Direction::Left)),
+        'R' => Some(Some(Direction::Right)),
+        '-' => Some(None),
+        _ => None,
+    }
+}
+
+fn parse_spawn(text: &str) -> Option<Spawn> {
+    let mut chars = text.chars();
+    let kind = chars.next()?;
+    let (x, y) = chars.as_str().split_once(',')?;
+    let point = Point { x: x.parse().ok()?, y: y.parse().ok()? };
+    match kind {
+        'F' => Some(Spawn::Food(point)),
+        'B' => Some(Spawn::Block(point)),
+        _ => None,
+    }
+}
+
+// Ticks between two snapshots kept by a Playback
+const KEYFRAME_INTERVAL: usize = 256;
+
+// Steps through a replay in either direction.
+// A snapshot of the game is kept every KEYFRAME_INTERVAL ticks, stepping back replays the ticks
+// since the last snapshot before it.
+pub struct Playback {
+    replay: Replay,
+    game: Game,
+    tick: usize,
+    // Game at tick n * KEYFRAME_INTERVAL
+    keyframes: Vec<Game>,
+    desync_at: Option<usize>,
+}
+
+impl Playback {
+    pub fn new(replay: Replay) -> Playback {
+        let settings = Settings {
+            grid_width: replay.width,
+            grid_height: replay.height,
+            wrap: replay.wrap,
+            head_on: replay.head_on,
+            food_tie: replay.food_tie,
+            ..Settings::default()
+        };
+        let level = replay.level.clone().unwrap_or_else(|| Arc::new(Level::open(replay.width, replay.height)));
+        let game = match replay.mode {
+            Mode::Competitive => Game::arena(replay.seed, &settings, level, replay.seats),
+            Mode::Single => Game::on_level(replay.mode, replay.seed, &settings, level),
+        };
+        Playback {
+            replay,
+            keyframes: vec![game.clone()],
+            game,
+            tick: 0,
+            desync_at: None,
+        }
+    }
+
+    pub fn game(&self) -> &Game {
+        &self.game
+    }
+
+    // Number of ticks played so far
+    pub fn tick(&self) -> usize {
+        self.tick
+    }
+
+    pub fn len(&self) -> usize {
+        self.replay.ticks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.replay.ticks.is_empty()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.tick() >= self.len()
+    }
+
+    // First tick whose spawns differ from the recording, if any
+    pub fn desync_at(&self) -> Option<usize> {
+        self.desync_at
+    }
+
+    pub fn step_forward(&mut self) -> bool {
+        let Some(tick) = self.replay.ticks.get(self.tick) else {
+            return false;
+        };
+        self.game.advance(tick.moves.clone());
+        if self.desync_at.is_none() && self.game.last_spawns() != tick.spawns.as_slice() {
+            self.desync_at = Some(self.tick);
+        }
+        self.tick += 1;
+        if self.tick == self.keyframes.len() * KEYFRAME_INTERVAL {
+            self.keyframes.push(self.game.clone());
+        }
+        true
+    }
+
+    pub fn step_back(&mut self) -> bool {
+        if self.tick == 0 {
+            return false;
+        }
+        let target = self.tick - 1;
+        let keyframe = target / KEYFRAME_INTERVAL;
+        self.game = self.keyframes[keyframe].clone();
+        for tick in &self.replay.ticks[keyframe * KEYFRAME_INTERVAL..target] {
+            self.game.advance(tick.moves.clone());
+        }
+        self.tick = target;
+        if self.desync_at.is_some_and(|tick| tick >= self.tick) {
+            self.desync_at = None;
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recording(level: Level) -> Replay {
+        let settings = Settings { grid_width: level.width, grid_height: level.height, ..Settings::default() };
+        let mut game = Game::on_level(Mode::Single, 7, &settings, Arc::new(level));
+        let mut replay = Replay::new(&game);
+        for dir in [Direction::Right, Direction::Down, Direction::Down] {
+            game.advance(Moves(vec![Some(dir)]));
+            replay.record(&game);
+        }
+        replay
+    }
+
+    #[test]
+    fn a_replay_carries_its_level() {
+        let level = Level::parse("not-in-levels", "title = \"Nowhere\"\nmap = \"\"\"\n.........\n....#....\n.*.......\n.........\n.........\n\"\"\"\n").unwrap();
+        let replay = recording(level);
+        let read = Replay::parse(&replay.to_string()).unwrap();
+        assert_eq!(read.to_string(), replay.to_string());
+        assert_eq!(read.level.as_ref().unwrap().walls, vec![Point { x: 4, y: 1 }]);
+    }
+
+    #[test]
+    fn older_replays_look_the_level_up_by_name() {
+        let replay = recording(Level::find("box").unwrap());
+        let text: String = replay.to_string().lines().filter(|line| !line.starts_with('|')).map(|line| format!("{}\n", line)).collect();
+        assert_eq!(Replay::parse(&text).unwrap().to_string(), replay.to_string());
+    }
+}
@@ -0,0 +1,53 @@
+// A game together with the strategies steering its snakes.
+
+use crate::game::{Game, Inputs, Moves, Seat, StepOutcome};
+use crate::strategy::Strategy;
+
+pub struct Session {
+    game: Game,
+    // One per seat of the game
+    strategies: Vec<Box<dyn Strategy>>,
+}
+
+impl Session {
+    // A single game when `bot` is None, a competitive one between the two otherwise
+    pub fn new(game: Game, player: Box<dyn Strategy>, bot: Option<Box<dyn Strategy>>) -> Session {
+        Self::arena(game, std::iter::once(player).chain(bot).collect())
+    }
+
+    // A game with a strategy for every seat, in seat order
+    pub fn arena(game: Game, strategies: Vec<Box<dyn Strategy>>) -> Session {
+        assert_eq!(strategies.len(), game.seats(), "every seat needs a strategy");
+        Session { game, strategies }
+    }
+
+    pub fn game(&self) -> &Game {
+        &self.game
+    }
+
+    pub fn strategy(&self, seat: Seat) -> &dyn Strategy {
+        self.strategies[seat.0].as_ref()
+    }
+
+    // Asks every strategy of a snake still alive for a move from the same board, then plays them
+    // all at once
+    pub fn step(&mut self, inputs: Inputs) -> StepOutcome {
+        let was_over = self.game.is_over();
+        let mut moves = Moves::default();
+        if !was_over {
+            for (i, strategy) in self.strategies.iter_mut().enumerate() {
+                let seat = Seat(i);
+                if self.game.death(seat).is_none() {
+                    moves.set(seat, strategy.choose(&self.game.view(seat, inputs.get(seat))));
+                }
+            }
+        }
+        let outcome = self.game.advance(moves);
+        if outcome == StepOutcome::GameOver && !was_over {
+            for (i, strategy) in self.strategies.iter_mut().enumerate() {
+                strategy.finish(&self.game.view(Seat(i), None));
+            }
+        }
+        outcome
+    }
+}
@@ -1,9 +1,182 @@
-// Settings for Rusty Snake. 
-// TOOD: Making clarity in structure 
-
-pub const CELL_SIZE: f32 = 20.0;
-pub const GRID_WIDTH: i32 = 50;
-pub const GRID_HEIGHT: i32 = 40;
-pub const WINDOW_WIDTH: f32 = CELL_SIZE * GRID_WIDTH as f32;
-pub const WINDOW_HEIGHT: f32 = CELL_SIZE * GRID_HEIGHT as f32;
-pub const MOVE_INTERVAL: f32 = 0.1;
+// Settings for Rusty Snake.
+// Loaded from a TOML file at startup, every field falls back to its default when left out:
+//
+//     cell_size = 20.0
+//     grid_width = 50
+//     grid_height = 40
+//     move_interval = 0.1
+//     move_timeout = 0.5
+//     wrap = false
+//     head_on = "longer-wins"
+//     food_tie = "nobody"
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+// Settings file picked up from the working directory when no other file is given
+pub const DEFAULT_SETTINGS_FILE: &str = "rusty_snake.toml";
+
+pub const MIN_GRID_SIZE: i32 = 5;
+pub const MAX_GRID_SIZE: i32 = 500;
+
+#[derive(Clone, Copy, PartialEq, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    // Size of a grid cell in pixels when the window opens
+    pub cell_size: f32,
+    pub grid_width: i32,
+    pub grid_height: i32,
+    // Seconds between two ticks
+    pub move_interval: f32,
+    // Seconds an external bot gets to answer before its snake carries on straight ahead
+    pub move_timeout: f32,
+    // Snakes leaving the board at one edge come back in on the opposite one instead of dying
+    pub wrap: bool,
+    // What becomes of competitive snakes whose heads move onto the same cell
+    pub head_on: HeadOn,
+    // Who scores food reached by several snakes at once when none of them survives
+    pub food_tie: FoodTie,
+}
+
+// Rule for snakes moving their heads onto the same cell in the same tick
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum HeadOn {
+    // The longest snake survives, snakes of equal length all die
+    #[default]
+    LongerWins,
+    // Every snake in the collision dies
+    BothDie,
+}
+
+impl HeadOn {
+    pub const ALL: [HeadOn; 2] = [HeadOn::LongerWins, HeadOn::BothDie];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            HeadOn::LongerWins => "longer-wins",
+            HeadOn::BothDie => "both-die",
+        }
+    }
+}
+
+impl FromStr for HeadOn {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<HeadOn, String> {
+        HeadOn::ALL.into_iter().find(|rule| rule.name() == s).ok_or(format!("unknown head-on rule {}, expected longer-wins or both-die", s))
+    }
+}
+
+// Rule for food that several snakes reach in the same tick without any of them surviving
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FoodTie {
+    // The food stays where it is and nobody scores it
+    #[default]
+    Nobody,
+    // Every snake that reached it scores it
+    Shared,
+}
+
+impl FoodTie {
+    pub const ALL: [FoodTie; 2] = [FoodTie::Nobody, FoodTie::Shared];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            FoodTie::Nobody => "nobody",
+            FoodTie::Shared => "shared",
+        }
+    }
+}
+
+impl FromStr for FoodTie {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<FoodTie, String> {
+        FoodTie::ALL.into_iter().find(|rule| rule.name() == s).ok_or(format!("unknown food tie rule {}, expected nobody or shared", s))
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            cell_size: 20.0,
+            grid_width: 50,
+            grid_height: 40,
+            move_interval: 0.1,
+            move_timeout: 0.5,
+            wrap: false,
+            head_on: HeadOn::default(),
+            food_tie: FoodTie::default(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum SettingsError {
+    Io(PathBuf, io::Error),
+    Parse(PathBuf, toml::de::Error),
+    Invalid(String),
+}
+
+impl fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SettingsError::Io(path, e) => write!(f, "could not read {}: {}", path.display(), e),
+            SettingsError::Parse(path, e) => write!(f, "could not parse {}: {}", path.display(), e),
+            SettingsError::Invalid(message) => write!(f, "invalid settings: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for SettingsError {}
+
+impl Settings {
+    pub fn window_width(&self) -> f32 {
+        self.cell_size * self.grid_width as f32
+    }
+
+    pub fn window_height(&self) -> f32 {
+        self.cell_size * self.grid_height as f32
+    }
+
+    pub fn load(path: &Path) -> Result<Settings, SettingsError> {
+        let text = fs::read_to_string(path).map_err(|e| SettingsError::Io(path.to_path_buf(), e))?;
+        let settings: Settings = toml::from_str(&text).map_err(|e| SettingsError::Parse(path.to_path_buf(), e))?;
+        settings.validate()?;
+        Ok(settings)
+    }
+
+    // Loads the given file, or the default file when it exists, or the defaults otherwise
+    pub fn load_or_default(path: Option<&Path>) -> Result<Settings, SettingsError> {
+        match path {
+            Some(path) => Self::load(path),
+            None if Path::new(DEFAULT_SETTINGS_FILE).exists() => Self::load(Path::new(DEFAULT_SETTINGS_FILE)),
+            None => Ok(Settings::default()),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), SettingsError> {
+        let invalid = |message: String| Err(SettingsError::Invalid(message));
+        if !(self.cell_size.is_finite() && self.cell_size > 0.0) {
+            return invalid(format!("cell_size must be a positive number, got {}", self.cell_size));
+        }
+        for (name, value) in [("grid_width", self.grid_width), ("grid_height", self.grid_height)] {
+            if !(MIN_GRID_SIZE..=MAX_GRID_SIZE).contains(&value) {
+                return invalid(format!("{} must be between {} and {}, got {}", name, MIN_GRID_SIZE, MAX_GRID_SIZE, value));
+            }
+        }
+        if !(self.move_interval.is_finite() && self.move_interval > 0.0) {
+            return invalid(format!("move_interval must be a positive number of seconds, got {}", self.move_interval));
+        }
+        if !(self.move_timeout.is_finite() && self.move_timeout > 0.0) {
+            return invalid(format!("move_timeout must be a positive number of seconds, got {}", self.move_timeout));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,231 @@
+// Headless batch runs for judging bots.
+// Plays many seeded games without a window, spread over a few threads, and sums up how they went.
+
+use std::fmt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::game::{Death, Game, Inputs, Mode, Seat, StepOutcome};
+use crate::level::Level;
+use crate::session::Session;
+use crate::settings::Settings;
+use crate::strategy::{StrategyError, StrategyKind};
+
+pub struct Simulation {
+    pub settings: Settings,
+    // Played on an open board when None
+    pub level: Option<Arc<Level>>,
+    pub player: StrategyKind,
+    // Plays competitive games against this strategy, single games otherwise
+    pub bot: Option<StrategyKind>,
+    pub games: usize,
+    // Game n is played with seed + n
+    pub seed: u64,
+    // Games still running after this many ticks are stopped and count as unfinished
+    pub max_ticks: u64,
+    pub threads: usize,
+}
+
+// How one snake ended a game
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct SnakeResult {
+    pub score: i32,
+    pub length: usize,
+    pub death: Option<Death>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct GameResult {
+    pub seed: u64,
+    pub ticks: u64,
+    // The single snake filled the board
+    pub perfect: bool,
+    pub player: SnakeResult,
+    pub bot: Option<SnakeResult>,
+}
+
+pub struct Report {
+    pub settings: Settings,
+    pub level: Option<Arc<Level>>,
+    pub player: String,
+    pub bot: Option<String>,
+    // Ordered by seed
+    pub results: Vec<GameResult>,
+    pub elapsed: Duration,
+}
+
+impl Simulation {
+    pub fn run(&self) -> Result<Report, StrategyError> {
+        // Fail on a bad strategy before any thread starts
+        let level = self.board_level();
+        self.player.build(&self.settings, &level)?;
+        if let Some(bot) = &self.bot {
+            bot.build(&self.settings, &level)?;
+        }
+
+        let start = Instant::now();
+        let seeds: Vec<u64> = (0..self.games).map(|n| self.seed.wrapping_add(n as u64)).collect();
+        let results = in_parallel(self.threads, &seeds, |seed| self.play(*seed))?;
+
+        Ok(Report {
+            settings: self.settings,
+            level: self.level.clone(),
+            player: self.player.name(),
+            bot: self.bot.as_ref().map(|bot| bot.name()),
+            results,
+            elapsed: start.elapsed(),
+        })
+    }
+
+    pub fn play(&self, seed: u64) -> Result<GameResult, StrategyError> {
+        let mode = if self.bot.is_some() { Mode::Competitive } else { Mode::Single };
+        let level = self.board_level();
+        let player = self.player.build(&self.settings, &level)?;
+        let bot = self.bot.as_ref().map(|bot| bot.build(&self.settings, &level)).transpose()?;
+        let game = Game::on_level(mode, seed, &self.settings, level);
+        let mut session = Session::new(game, player, bot);
+        let mut ticks = 0;
+        while ticks < self.max_ticks {
+            ticks += 1;
+            if session.step(Inputs::default()) == StepOutcome::GameOver {
+                break;
+            }
+        }
+        let game = session.game();
+        let result = |seat: Seat| {
+            let view = game.view(seat, None);
+            SnakeResult { score: view.score, length: view.snake.len(), death: game.death(seat) }
+        };
+        Ok(GameResult {
+            seed,
+            ticks,
+            perfect: game.is_perfect(),
+            player: result(Seat::PLAYER),
+            bot: self.bot.as_ref().map(|_| result(Seat::BOT)),
+        })
+    }
+
+    fn board_level(&self) -> Arc<Level> {
+        self.level.clone().unwrap_or_else(|| Arc::new(Level::open(self.settings.grid_width, self.settings.grid_height)))
+    }
+}
+
+// Runs `job` on every item over up to `threads` threads, results come back in the order of `items`
+pub fn in_parallel<T: Sync, R: Send, E: Send>(threads: usize, items: &[T], job: impl Fn(&T) -> Result<R, E> + Sync) -> Result<Vec<R>, E> {
+    let next = AtomicUsize::new(0);
+    let threads = threads.clamp(1, items.len().max(1));
+    let chunks = thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut results = vec![];
+                    loop {
+                        let n = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(item) = items.get(n) else {
+                            return Ok(results);
+                        };
+                        results.push((n, job(item)?));
+                    }
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .map(|worker| worker.join().expect("simulation thread panicked"))
+            .collect::<Result<Vec<Vec<(usize, R)>>, E>>()
+    })?;
+    let mut results: Vec<(usize, R)> = chunks.into_iter().flatten().collect();
+    results.sort_by_key(|(n, _)| *n);
+    Ok(results.into_iter().map(|(_, result)| result).collect())
+}
+
+impl Report {
+    pub fn games_per_second(&self) -> f64 {
+        self.results.len() as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+
+    // Games stopped by the tick limit with every snake still alive
+    pub fn unfinished(&self) -> usize {
+        self.results
+            .iter()
+            .filter(|r| !r.perfect && r.player.death.is_none() && r.bot.is_none_or(|bot| bot.death.is_none()))
+            .count()
+    }
+
+    // Games won by filling the board
+    pub fn perfect(&self) -> usize {
+        self.results.iter().filter(|r| r.perfect).count()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let games = self.results.len();
+        let ticks: u64 = self.results.iter().map(|r| r.ticks).sum();
+        let matchup = match &self.bot {
+            Some(bot) => format!("{} vs {}", self.player, bot),
+            None => self.player.clone(),
+        };
+        match &self.level {
+            Some(level) => writeln!(f, "{} games of {} on {} ({}x{})", games, matchup, level.title, level.width, level.height)?,
+            None => writeln!(f, "{} games of {} on a {}x{} board", games, matchup, self.settings.grid_width, self.settings.grid_height)?,
+        }
+        writeln!(
+            f,
+            "{:.2}s, {:.1} games/s, {} ticks, {} perfect, {} unfinished",
+            self.elapsed.as_secs_f64(),
+            self.games_per_second(),
+            ticks,
+            self.perfect(),
+            self.unfinished(),
+        )?;
+
+        let player: Vec<SnakeResult> = self.results.iter().map(|r| r.player).collect();
+        write_snake(f, &self.player, &player)?;
+        if let Some(bot) = &self.bot {
+            let results: Vec<SnakeResult> = self.results.iter().filter_map(|r| r.bot).collect();
+            write_snake(f, bot, &results)?;
+        }
+        Ok(())
+    }
+}
+
+fn write_snake(f: &mut fmt::Formatter, name: &str, results: &[SnakeResult]) -> fmt::Result {
+    if results.is_empty() {
+        return Ok(());
+    }
+    let mut scores: Vec<i32> = results.iter().map(|r| r.score).collect();
+    scores.sort_unstable();
+    let percentile = |p: usize| scores[(scores.len() - 1) * p / 100];
+    let mean = |values: &mut dyn Iterator<Item = f64>| values.sum::<f64>() / results.len() as f64;
+
+    writeln!(f)?;
+    writeln!(f, "{}", name)?;
+    writeln!(
+        f,
+        "  score   min {}  p10 {}  p25 {}  median {}  p75 {}  p90 {}  max {}  mean {:.1}",
+        percentile(0),
+        percentile(10),
+        percentile(25),
+        percentile(50),
+        percentile(75),
+        percentile(90),
+        percentile(100),
+        mean(&mut scores.iter().map(|s| *s as f64)),
+    )?;
+    writeln!(
+        f,
+        "  length  mean {:.1}  max {}",
+        mean(&mut results.iter().map(|r| r.length as f64)),
+        results.iter().map(|r| r.length).max().unwrap_or(0),
+    )?;
+    write!(f, "  deaths")?;
+    let share = |count: usize| 100.0 * count as f64 / results.len() as f64;
+    for death in Death::ALL {
+        let count = results.iter().filter(|r| r.death == Some(death)).count();
+        write!(f, "  {} {} ({:.1}%)", death.name(), count, share(count))?;
+    }
+    let alive = results.iter().filter(|r| r.death.is_none()).count();
+    writeln!(f, "  alive {} ({:.1}%)", alive, share(alive))
+}
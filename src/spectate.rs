@@ -0,0 +1,278 @@
+// Live streams of running games for spectators.
+// A broadcast listens for spectators while games are played and sends each of them the game so
+// far, then every tick as it is played. Like a replay it holds the seed and the moves, which is
+// enough for a spectator to rebuild every board. A hash of the board goes along with every tick
+// so a spectator notices when its copy drifts. Lines of JSON, one per message:
+//
+//     {"type":"hello","protocol":3,"mode":"competitive","snakes":2,"seed":42,"wrap":false,"head_on":"longer-wins","food_tie":"nobody","level":"box","map":"...","names":["Host","Guest"]}
+//     {"type":"tick","moves":["up",null],"hash":1234567890}
+//
+// A new hello starts the next game on the same stream.
+
+use serde::{Deserialize, Serialize};
+use std::io::{self, BufRead, BufReader, Write};
+use std::mem;
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{self, Receiver, SyncSender, TryRecvError};
+use std::thread;
+use std::time::Duration;
+use crate::game::{Direction, Game, MAX_SNAKES, Mode, Moves};
+use crate::level::Level;
+use crate::net::NetError;
+use crate::settings::{FoodTie, HeadOn, Settings};
+
+pub const DEFAULT_SPECTATE_PORT: u16 = 7879;
+
+const PROTOCOL: u32 = 3;
+
+// A spectator that does not take a line within this long is dropped. Only the thread writing to
+// it waits that long, the game just queues its lines.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+
+// Lines queued for a spectator, one that falls further behind is dropped
+const QUEUE_LENGTH: usize = 1024;
+
+// Lines of a game kept for spectators who come in late, past them a newcomer waits for the next game
+const HISTORY_LENGTH: usize = 100_000;
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum Frame {
+    Hello {
+        protocol: u32,
+        mode: String,
+        snakes: usize,
+        seed: u64,
+        wrap: bool,
+        head_on: HeadOn,
+        food_tie: FoodTie,
+        level: String,
+        map: String,
+        names: Vec<String>,
+    },
+    // A move for every seat
+    Tick { moves: Vec<Option<Direction>>, hash: u64 },
+}
+
+#[derive(Default)]
+struct Audience {
+    // Lines queued for each spectator, whose own thread writes them out
+    spectators: Vec<SyncSender<String>>,
+    // Spectators who came in too late to catch up, they join with the next game
+    waiting: Vec<SyncSender<String>>,
+    // Every line of the current game, sent to spectators who come in late. None once the game
+    // outgrew HISTORY_LENGTH, or before the first one.
+    history: Option<Vec<String>>,
+}
+
+impl Audience {
+    // Never waits on a connection, spectators whose thread gave up or fell behind are dropped here
+    fn send(&mut self, line: String) {
+        self.spectators.retain(|spectator| spectator.try_send(line.clone()).is_ok());
+        if self.history.as_ref().is_some_and(|history| history.len() >= HISTORY_LENGTH) {
+            self.history = None;
+        }
+        if let Some(history) = &mut self.history {
+            history.push(line);
+        }
+    }
+}
+
+// Writes out the game so far and then the lines queued for one spectator, until it stops taking
+// them or the broadcast ends
+fn serve(mut stream: TcpStream, history: Vec<String>, lines: Receiver<String>) {
+    if stream.set_nodelay(true).and_then(|_| stream.set_write_timeout(Some(WRITE_TIMEOUT))).is_err() {
+        return;
+    }
+    for line in history.into_iter().chain(lines) {
+        if writeln!(stream, "{}", line).and_then(|_| stream.flush()).is_err() {
+            return;
+        }
+    }
+}
+
+// The sending end, fed by whoever plays the games
+pub struct Broadcast {
+    port: u16,
+    audience: Arc<Mutex<Audience>>,
+}
+
+impl Broadcast {
+    // Lets spectators connect on `port` from now on
+    pub fn start(port: u16) -> io::Result<Broadcast> {
+        let listener = TcpListener::bind(("0.0.0.0", port))?;
+        let audience = Arc::new(Mutex::new(Audience::default()));
+        let shared = audience.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().filter_map(|stream| stream.ok()) {
+                let (spectator, lines) = mpsc::sync_channel(QUEUE_LENGTH);
+                let mut audience = shared.lock().expect("no thread panics holding the audience");
+                // The game so far, taken together with joining so no tick is missed or sent twice
+                let history = audience.history.clone();
+                match history {
+                    Some(_) => audience.spectators.push(spectator),
+                    None => audience.waiting.push(spectator),
+                }
+                drop(audience);
+                thread::spawn(move || serve(stream, history.unwrap_or_default(), lines));
+            }
+        });
+        Ok(Broadcast { port, audience })
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    pub fn spectators(&self) -> usize {
+        let audience = self.audience.lock().expect("no thread panics holding the audience");
+        audience.spectators.len() + audience.waiting.len()
+    }
+
+    // Announces a new game, `names` are those of the snakes in seat order
+    pub fn start_game(&self, game: &Game, names: &[String]) {
+        let level = game.level();
+        let hello = Frame::Hello {
+            protocol: PROTOCOL,
+            mode: mode_name(game.mode()).to_string(),
+            snakes: game.seats(),
+            seed: game.seed(),
+            wrap: game.wraps(),
+            head_on: game.head_on(),
+            food_tie: game.food_tie(),
+            level: level.name.clone(),
+            map: level.to_toml(),
+            names: names.to_vec(),
+        };
+        let mut audience = self.audience.lock().expect("no thread panics holding the audience");
+        let waiting = mem::take(&mut audience.waiting);
+        audience.spectators.extend(waiting);
+        audience.history = Some(vec![]);
+        audience.send(serde_json::to_string(&hello).expect("frames always serialize"));
+    }
+
+    // Sends the tick the game has just played
+    pub fn record(&self, game: &Game) {
+        let tick = Frame::Tick { moves: game.last_moves().0.clone(), hash: game.state_hash() };
+        let mut audience = self.audience.lock().expect("no thread panics holding the audience");
+        audience.send(serde_json::to_string(&tick).expect("frames always serialize"));
+    }
+}
+
+// The receiving end, rebuilding the games it is sent
+pub struct Spectator {
+    game: Game,
+    names: Vec<String>,
+    // Frames read by a background thread
+    incoming: Receiver<Result<Frame, NetError>>,
+}
+
+impl Spectator {
+    // Connects to the broadcast at `address` and waits for the first game to start
+    pub fn connect(address: impl ToSocketAddrs) -> Result<Spectator, NetError> {
+        let stream = TcpStream::connect(address)?;
+        let (sender, incoming) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stream).lines() {
+                let frame = line.map_err(NetError::from).and_then(|line| parse(&line));
+                let failed = frame.is_err();
+                if sender.send(frame).is_err() || failed {
+                    break;
+                }
+            }
+        });
+        let first = incoming.recv().map_err(|_| NetError::Closed)??;
+        let (game, names) = new_game(first)?;
+        Ok(Spectator { game, names, incoming })
+    }
+
+    pub fn game(&self) -> &Game {
+        &self.game
+    }
+
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    // Plays every tick that came in since the last call, returns whether the board changed
+    pub fn poll(&mut self) -> Result<bool, NetError> {
+        let mut changed = false;
+        loop {
+            let frame = match self.incoming.try_recv() {
+                Ok(frame) => frame?,
+                Err(TryRecvError::Empty) => return Ok(changed),
+                Err(TryRecvError::Disconnected) => return Err(NetError::Closed),
+            };
+            changed = true;
+            match frame {
+                Frame::Hello { .. } => (self.game, self.names) = new_game(frame)?,
+                Frame::Tick { moves, hash } => {
+                    self.game.advance(Moves(moves));
+                    if self.game.state_hash() != hash {
+                        return Err(NetError::Desync(self.game.ticks()));
+                    }
+                },
+            }
+        }
+    }
+}
+
+fn new_game(hello: Frame) -> Result<(Game, Vec<String>), NetError> {
+    let Frame::Hello { protocol, mode, snakes, seed, wrap, head_on, food_tie, level, map, names } = hello else {
+        return Err(NetError::Protocol("a tick before the game started".to_string()));
+    };
+    if protocol != PROTOCOL {
+        return Err(NetError::Protocol(format!("the broadcast speaks protocol {}, this build {}", protocol, PROTOCOL)));
+    }
+    let level = Arc::new(Level::parse(&level, &map).map_err(NetError::Level)?);
+    let settings = Settings { wrap, head_on, food_tie, ..level.settings(&Settings::default()) };
+    let game = match (mode.as_str(), snakes) {
+        ("single", 1) => Game::on_level(Mode::Single, seed, &settings, level),
+        ("competitive", 2..=MAX_SNAKES) => Game::arena(seed, &settings, level, snakes),
+        _ => return Err(NetError::Protocol(format!("no {} game for {} snakes", mode, snakes))),
+    };
+    Ok((game, names))
+}
+
+fn mode_name(mode: Mode) -> &'static str {
+    match mode {
+        Mode::Single => "single",
+        Mode::Competitive => "competitive",
+    }
+}
+
+fn parse(line: &str) -> Result<Frame, NetError> {
+    serde_json::from_str(line.trim()).map_err(|e| NetError::Protocol(format!("{} in {:?}", e, line.trim())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+    use crate::game::Direction;
+
+    #[test]
+    fn a_late_spectator_catches_up() {
+        let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let broadcast = Broadcast::start(port).unwrap();
+        let settings = Settings::default();
+        let level = Arc::new(Level::open(settings.grid_width, settings.grid_height));
+        let mut game = Game::arena(42, &settings, level, 2);
+        broadcast.start_game(&game, &["Player".to_string(), "Bot".to_string()]);
+        for _ in 0..5 {
+            game.advance(Moves::pair(Some(Direction::Up), Some(Direction::Down)));
+            broadcast.record(&game);
+        }
+
+        let mut spectator = Spectator::connect(("127.0.0.1", port)).unwrap();
+        game.advance(Moves::pair(Some(Direction::Left), Some(Direction::Right)));
+        broadcast.record(&game);
+        let started = Instant::now();
+        while spectator.game().ticks() < game.ticks() && started.elapsed() < Duration::from_secs(5) {
+            spectator.poll().unwrap();
+        }
+        assert_eq!(spectator.game().state_hash(), game.state_hash());
+        assert_eq!(broadcast.spectators(), 1);
+    }
+}
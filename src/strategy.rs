@@ -0,0 +1,424 @@
+// Controllers for the Rusty Snake snakes.
+// Every snake, human or bot, is steered by a strategy that looks at the board and picks a move.
+
+use std::cmp;
+use std::collections::VecDeque;
+use std::fmt;
+use std::io;
+use std::mem;
+use std::str::FromStr;
+use std::time::Duration;
+use crate::battlesnake::BattlesnakeClient;
+use crate::bot::{self, HamiltonianCycle, NoCycle};
+use crate::external::External;
+use crate::game::{Direction, Point, View};
+use crate::level::Level;
+use crate::settings::Settings;
+
+pub trait Strategy: Send {
+    fn name(&self) -> String;
+
+    // Next move for the snake seen in `view`, `None` gives up and ends the game for this snake
+    fn choose(&mut self, view: &View) -> Option<Direction>;
+
+    // Called once with the final board when the game is over
+    fn finish(&mut self, _view: &View) {}
+}
+
+// Strategies that can be picked from the menu or the command line
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum StrategyKind {
+    // Arrow keys, or WASD for a second player
+    Human,
+    // Heads straight for the food, guarded by a flood fill
+    Greedy,
+    // A* to the food, guarded by a flood fill
+    Pathfinder,
+    // Follows a Hamiltonian cycle and only cuts corners when that is safe
+    Hamiltonian,
+    // Another program, given as `exec:<command>`, see external.rs
+    External(String),
+    // A Battlesnake server, given by its http:// address, see battlesnake.rs
+    Battlesnake(String),
+}
+
+#[derive(Debug)]
+pub enum StrategyError {
+    NoCycle(NoCycle),
+    // The level, whose walls a Hamiltonian cycle would run through
+    Walls(String),
+    Spawn(String, io::Error),
+    Connect(String, Box<ureq::Error>),
+}
+
+impl fmt::Display for StrategyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StrategyError::NoCycle(e) => write!(f, "{}", e),
+            StrategyError::Walls(level) => write!(f, "the hamiltonian bot cannot play on level {}, its cycle would run through the walls", level),
+            StrategyError::Spawn(command, e) => write!(f, "could not start {}: {}", command, e),
+            StrategyError::Connect(url, e) => write!(f, "could not reach {}: {}", url, e),
+        }
+    }
+}
+
+impl std::error::Error for StrategyError {}
+
+impl From<NoCycle> for StrategyError {
+    fn from(e: NoCycle) -> StrategyError {
+        StrategyError::NoCycle(e)
+    }
+}
+
+impl StrategyKind {
+    pub fn name(&self) -> String {
+        match self {
+            StrategyKind::Human => "human".to_string(),
+            StrategyKind::Greedy => "greedy".to_string(),
+            StrategyKind::Pathfinder => "pathfinder".to_string(),
+            StrategyKind::Hamiltonian => "hamiltonian".to_string(),
+            StrategyKind::External(command) => format!("exec:{}", command),
+            StrategyKind::Battlesnake(url) => url.clone(),
+        }
+    }
+
+    // Bots are built for the board of `level`, with its size in `settings`
+    pub fn build(&self, settings: &Settings, level: &Level) -> Result<Box<dyn Strategy>, StrategyError> {
+        Ok(match self {
+            StrategyKind::Human => Box::new(Human),
+            StrategyKind::Greedy => Box::new(Greedy),
+            StrategyKind::Pathfinder => Box::new(Pathfinder),
+            StrategyKind::Hamiltonian if !level.walls.is_empty() => return Err(StrategyError::Walls(level.name.clone())),
+            StrategyKind::Hamiltonian => Box::new(Hamiltonian::new(settings.grid_width, settings.grid_height)?),
+            StrategyKind::External(command) => {
+                let timeout = Duration::from_secs_f32(settings.move_timeout);
+                Box::new(External::spawn(command, timeout).map_err(|e| StrategyError::Spawn(command.clone(), e))?)
+            },
+            StrategyKind::Battlesnake(url) => {
+                let timeout = Duration::from_secs_f32(settings.move_timeout);
+                Box::new(BattlesnakeClient::connect(url, timeout).map_err(|e| StrategyError::Connect(url.clone(), e))?)
+            },
+        })
+    }
+}
+
+impl fmt::Display for StrategyKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for StrategyKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<StrategyKind, String> {
+        match s {
+            "human" => Ok(StrategyKind::Human),
+            "greedy" => Ok(StrategyKind::Greedy),
+            "pathfinder" => Ok(StrategyKind::Pathfinder),
+            "hamiltonian" => Ok(StrategyKind::Hamiltonian),
+            _ if s.starts_with("http://") => Ok(StrategyKind::Battlesnake(s.to_string())),
+            _ => match s.strip_prefix("exec:") {
+                Some(command) if !command.trim().is_empty() => Ok(StrategyKind::External(command.trim().to_string())),
+                _ => Err(format!("unknown strategy {}, expected human, greedy, pathfinder, hamiltonian, exec:<command> or http://<address>", s)),
+            },
+        }
+    }
+}
+
+// Turns towards the key pressed last, a snake can never reverse straight into its own neck
+pub struct Human;
+
+impl Strategy for Human {
+    fn name(&self) -> String {
+        "human".to_string()
+    }
+
+    fn choose(&mut self, view: &View) -> Option<Direction> {
+        match view.input {
+            Some(dir) if dir != view.heading.opposite() => Some(dir),
+            _ => Some(view.heading),
+        }
+    }
+}
+
+// Takes the move closest to the food as long as the food can be reached at all
+pub struct Greedy;
+
+impl Strategy for Greedy {
+    fn name(&self) -> String {
+        "greedy".to_string()
+    }
+
+    fn choose(&mut self, view: &View) -> Option<Direction> {
+        let head = view.snake[0];
+        let candidates = candidates(view);
+        let food_reachable = bot::astar(view.board, head, view.food, |p| view.is_occupied(p)).is_some();
+        let towards_food = if food_reachable {
+            candidates
+                .iter()
+                .filter(|c| c.room >= view.snake.len())
+                .min_by_key(|c| view.distance(c.cell, view.food))
+                .map(|c| c.dir)
+        } else {
+            None
+        };
+        safest_move(view, &candidates, towards_food)
+    }
+}
+
+// Follows the shortest path to the food around the body, the block and other snakes
+pub struct Pathfinder;
+
+impl Strategy for Pathfinder {
+    fn name(&self) -> String {
+        "pathfinder".to_string()
+    }
+
+    fn choose(&mut self, view: &View) -> Option<Direction> {
+        let head = view.snake[0];
+        let path = bot::astar(view.board, head, view.food, |p| p == view.block || view.is_occupied(p));
+        let towards_food = match path.and_then(|path| path.first().copied()) {
+            Some(next) => view.board.direction_to(head, next),
+            // No way to the food, so just head in its general direction
+            None => greedy_move(view),
+        };
+        let candidates = candidates(view);
+        safest_move(view, &candidates, towards_food)
+    }
+}
+
+pub struct Hamiltonian {
+    cycle: HamiltonianCycle,
+    // The cycle rerouted around the block, rebuilt whenever the block moves, and whether it had
+    // to leave out a cell the block boxed in
+    route: Option<(Point, HamiltonianCycle, bool)>,
+    // The moves planned by the endgame search for the food at the given cell
+    plan: Option<(Point, VecDeque<Direction>)>,
+}
+
+impl Hamiltonian {
+    pub fn new(width: i32, height: i32) -> Result<Hamiltonian, NoCycle> {
+        Ok(Hamiltonian { cycle: HamiltonianCycle::new(width, height)?, route: None, plan: None })
+    }
+}
+
+impl Strategy for Hamiltonian {
+    fn name(&self) -> String {
+        "hamiltonian".to_string()
+    }
+
+    fn choose(&mut self, view: &View) -> Option<Direction> {
+        if self.route.as_ref().is_none_or(|(block, _, _)| *block != view.block) {
+            // Without a route around the block the bot keeps to the plain cycle and steps around it
+            let route = self.cycle.around(view.block).unwrap_or_else(|| self.cycle.clone());
+            let boxed_in = (0..view.height)
+                .flat_map(|y| (0..view.width).map(move |x| Point { x, y }))
+                .any(|p| p != view.block && !route.covers(p));
+            self.route = Some((view.block, route, boxed_in));
+        }
+        let (_, route, boxed_in) = self.route.as_ref().expect("the route was just built");
+        let head = view.snake[0];
+        // A boxed in cell could only ever be the last one filled, and with nobody else around the
+        // block stays where it is until something runs into it. So the snake does that itself,
+        // right away while it is still short and has little to lose.
+        if *boxed_in && view.opponents.is_empty() {
+            let path = bot::astar(view.board, head, view.block, |p| view.is_occupied(p));
+            if let Some(next) = path.and_then(|path| path.first().copied()) {
+                return view.board.direction_to(head, next);
+            }
+        }
+        let to_food = || {
+            let path = bot::astar(view.board, head, view.food, |p| p == view.block || view.is_occupied(p)).unwrap_or_default();
+            let mut from = head;
+            path.into_iter().filter_map(|p| view.board.direction_to(mem::replace(&mut from, p), p)).collect::<Vec<_>>()
+        };
+        if view.opponents.is_empty() {
+            if self.plan.as_ref().is_none_or(|(food, _)| *food != view.food) {
+                let moves = match bot::endgame(view.board, view.snake, view.food, view.block) {
+                    Some((odds, moves)) if odds > 0.0 => moves,
+                    // The board can no longer be filled, so the snake eats what it can get
+                    Some(_) => to_food(),
+                    None => vec![],
+                };
+                self.plan = Some((view.food, moves.into()));
+            }
+            if let Some(dir) = self.plan.as_mut().and_then(|(_, moves)| moves.pop_front()) {
+                return Some(dir);
+            }
+        }
+        let next = if route.holds(view.snake) {
+            route.next_move(view.snake, view.food, |p| p == view.block || view.is_occupied(p))
+        } else {
+            // The snake only leaves the route in a game that is lost, once the block has cut it
+            // in half and moved on. So here it eats what it can get as well.
+            to_food().first().copied().or_else(|| greedy_move(view))
+        };
+        // Being cut in half beats running into a body when the block is the only way on
+        next.or_else(|| Direction::ALL.into_iter().find(|dir| view.board.step(head, *dir) == view.block))
+    }
+}
+
+// A move a bot could make, with the number of cells it can still reach afterwards
+struct Candidate {
+    dir: Direction,
+    cell: Point,
+    room: usize,
+}
+
+// Closest free neighbour to the food, without looking any further ahead
+fn greedy_move(view: &View) -> Option<Direction> {
+    let head = view.snake[0];
+    let mut safe_moves = vec![];
+    for dir in Direction::ALL {
+        let p = view.step(head, dir);
+        if !view.in_bounds(p) {
+            continue;
+        }
+        if view.is_occupied(p) {
+            continue;
+        }
+        safe_moves.push((dir, p));
+    }
+    safe_moves.sort_by_key(|(_, p)| view.distance(*p, view.food));
+    safe_moves.first().map(|(dir, _)| *dir)
+}
+
+// Every move that does not kill the snake right away, with the room it leaves.
+// The tail moves out of the way on the next tick, the rest of the board stays put.
+fn candidates(view: &View) -> Vec<Candidate> {
+    let head = view.snake[0];
+    let tail = view.snake[view.snake.len() - 1];
+    let blocked = |p: Point| p != tail && view.is_occupied(p);
+    let mut candidates = vec![];
+    for dir in Direction::ALL {
+        let cell = view.step(head, dir);
+        if !view.in_bounds(cell) || view.is_occupied(cell) {
+            continue;
+        }
+        let room = bot::reachable_area(view.board, cell, blocked, view.snake.len());
+        candidates.push(Candidate { dir, cell, room });
+    }
+    candidates
+}
+
+// Takes `preferred` when it leaves room for the whole snake.
+// Otherwise the snake follows its own tail, which always opens up again,
+// and as a last resort takes the move with the most room.
+fn safest_move(view: &View, candidates: &[Candidate], preferred: Option<Direction>) -> Option<Direction> {
+    let roomy = |dir: Direction| candidates.iter().any(|c| c.dir == dir && c.room >= view.snake.len());
+    if let Some(dir) = preferred.filter(|dir| roomy(*dir)) {
+        return Some(dir);
+    }
+    if let Some(dir) = tail_move(view) {
+        return Some(dir);
+    }
+    candidates
+        .iter()
+        .max_by_key(|c| (c.room, cmp::Reverse(view.distance(c.cell, view.food))))
+        .map(|c| c.dir)
+}
+
+// First step on the shortest path to the snake's own tail
+fn tail_move(view: &View) -> Option<Direction> {
+    let snake = view.snake;
+    let head = snake[0];
+    let tail = snake[snake.len() - 1];
+    if snake.len() < 2 {
+        return None;
+    }
+    let path = bot::astar(view.board, head, tail, |p| p != tail && view.is_occupied(p))?;
+    // The tail only moves after the head, so it cannot be entered straight away
+    if path.len() < 2 {
+        return None;
+    }
+    view.board.direction_to(head, path[0])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::Board;
+    use crate::game::{Body, Game, Mode, Moves, Seat};
+
+    fn p(x: i32, y: i32) -> Point {
+        Point { x, y }
+    }
+
+    // A single player view of `snake` on `board`, which the snake has to cover already
+    fn view<'a>(board: &'a Board, snake: &'a Body, food: Point, block: Point) -> View<'a> {
+        View {
+            seat: Seat::PLAYER,
+            width: board.width(),
+            height: board.height(),
+            snake,
+            heading: Direction::Right,
+            score: 0,
+            opponents: vec![],
+            food,
+            block,
+            board,
+            input: None,
+        }
+    }
+
+    fn board_with(width: i32, height: i32, snake: &Body) -> Board {
+        let mut board = Board::new(width, height, false);
+        for p in snake {
+            board.occupy(*p, Seat::PLAYER);
+        }
+        board
+    }
+
+    fn self_play(width: i32, height: i32, seed: u64) -> Game {
+        let settings = Settings { grid_width: width, grid_height: height, ..Settings::default() };
+        let mut game = Game::new(Mode::Single, seed, &settings);
+        let mut bot = Hamiltonian::new(width, height).unwrap();
+        while !game.is_over() {
+            let dir = bot.choose(&game.view(Seat::PLAYER, None));
+            game.advance(Moves::pair(dir, None));
+        }
+        game
+    }
+
+    // Row 0 is a pocket of five cells the body closes off, the snake is longer than that
+    fn pocket() -> Body {
+        [p(0, 1), p(1, 1), p(2, 1), p(3, 1), p(4, 1), p(4, 2), p(3, 2), p(2, 2), p(1, 2)].into()
+    }
+
+    #[test]
+    fn candidates_measure_the_room_behind_each_move() {
+        let snake = pocket();
+        let board = board_with(5, 5, &snake);
+        let view = view(&board, &snake, p(4, 0), p(4, 4));
+        let rooms: Vec<(Direction, usize)> = candidates(&view).iter().map(|c| (c.dir, c.room)).collect();
+        assert_eq!(rooms, vec![(Direction::Up, 5), (Direction::Down, snake.len())]);
+    }
+
+    #[test]
+    fn bots_stay_out_of_a_pocket_too_small_for_the_snake() {
+        let snake = pocket();
+        let board = board_with(5, 5, &snake);
+        let view = view(&board, &snake, p(4, 0), p(4, 4));
+        assert_eq!(safest_move(&view, &candidates(&view), Some(Direction::Up)), Some(Direction::Down));
+        assert_eq!(Greedy.choose(&view), Some(Direction::Down));
+        assert_eq!(Pathfinder.choose(&view), Some(Direction::Down));
+    }
+
+    #[test]
+    fn the_hamiltonian_bot_fills_even_and_odd_boards() {
+        for (width, height, seed) in [(6, 6, 5), (10, 10, 10), (5, 6, 3), (12, 9, 2)] {
+            let game = self_play(width, height, seed);
+            assert!(game.is_perfect(), "{}x{} seed {}: {:?}", width, height, seed, game.death(Seat::PLAYER));
+            assert_eq!(game.snake().len(), (width * height) as usize - 1);
+        }
+    }
+
+    #[test]
+    fn the_hamiltonian_bot_takes_the_block_over_a_body() {
+        let snake: Body = [p(0, 1), p(0, 0), p(1, 0), p(2, 0), p(2, 1), p(2, 2), p(1, 2), p(0, 2)].into();
+        let board = board_with(4, 3, &snake);
+        let mut bot = Hamiltonian::new(4, 3).unwrap();
+        assert_eq!(bot.choose(&view(&board, &snake, p(3, 1), p(1, 1))), Some(Direction::Right));
+    }
+}
@@ -0,0 +1,416 @@
+// Tournaments between competitive strategies.
+// Every pairing plays the same seeds twice, once from each side of the board, so neither entrant
+// gets the better start. A snake that outlives the other wins. When both die on the same tick,
+// or the tick limit stops the game, the higher score wins and equal scores are a tie.
+
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use crate::level::Level;
+use crate::settings::Settings;
+use crate::simulate::{self, GameResult, Simulation};
+use crate::strategy::{StrategyError, StrategyKind};
+
+pub const START_ELO: f64 = 1500.0;
+const ELO_K: f64 = 32.0;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Format {
+    // Everyone plays everyone once
+    RoundRobin,
+    // Each round pairs entrants with the same number of points, avoiding rematches while it can.
+    // With an odd number of entrants one of them gets a bye worth a win.
+    Swiss { rounds: usize },
+}
+
+pub struct Tournament {
+    pub settings: Settings,
+    // Played on an open board when None
+    pub level: Option<Arc<Level>>,
+    pub entrants: Vec<StrategyKind>,
+    pub format: Format,
+    // Seeds per pairing, each one played from both sides
+    pub games: usize,
+    pub seed: u64,
+    pub max_ticks: u64,
+    pub threads: usize,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Outcome {
+    Win,
+    Loss,
+    Tie,
+}
+
+// One game from the point of view of the entrant in the player seat
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Match {
+    pub round: usize,
+    pub player: usize,
+    pub bot: usize,
+    pub result: GameResult,
+}
+
+impl Match {
+    pub fn outcome(&self) -> Outcome {
+        let bot = self.result.bot.expect("tournament games are competitive");
+        let player = self.result.player;
+        match (player.death.is_some(), bot.death.is_some()) {
+            (false, true) => Outcome::Win,
+            (true, false) => Outcome::Loss,
+            _ if player.score > bot.score => Outcome::Win,
+            _ if player.score < bot.score => Outcome::Loss,
+            _ => Outcome::Tie,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize)]
+pub struct Standing {
+    pub name: String,
+    pub games: usize,
+    pub wins: usize,
+    pub losses: usize,
+    pub ties: usize,
+    // Swiss rounds sat out, each worth a point without a game
+    pub byes: usize,
+    // A win or a bye is a point, a tie half a point
+    pub points: f64,
+    pub elo: f64,
+    pub score_for: i64,
+    pub score_against: i64,
+}
+
+impl Standing {
+    // Average score lead over the opponent per game
+    pub fn margin(&self) -> f64 {
+        if self.games == 0 {
+            0.0
+        } else {
+            (self.score_for - self.score_against) as f64 / self.games as f64
+        }
+    }
+}
+
+pub struct TournamentReport {
+    pub settings: Settings,
+    pub level: Option<Arc<Level>>,
+    pub format: Format,
+    // Best first
+    pub standings: Vec<Standing>,
+    pub matches: Vec<Match>,
+    pub elapsed: Duration,
+}
+
+impl Tournament {
+    pub fn run(&self) -> Result<TournamentReport, StrategyError> {
+        let level = self.level.clone().unwrap_or_else(|| Arc::new(Level::open(self.settings.grid_width, self.settings.grid_height)));
+        for entrant in &self.entrants {
+            entrant.build(&self.settings, &level)?;
+        }
+        let start = Instant::now();
+        let mut standings: Vec<Standing> = self
+            .entrants
+            .iter()
+            .map(|entrant| Standing {
+                name: entrant.name(),
+                games: 0,
+                wins: 0,
+                losses: 0,
+                ties: 0,
+                byes: 0,
+                points: 0.0,
+                elo: START_ELO,
+                score_for: 0,
+                score_against: 0,
+            })
+            .collect();
+        let mut matches = vec![];
+        let mut played = HashSet::new();
+        let mut byes = HashSet::new();
+
+        let rounds = match self.format {
+            Format::RoundRobin => 1,
+            Format::Swiss { rounds } => rounds,
+        };
+        for round in 0..rounds {
+            let pairings = match self.format {
+                Format::RoundRobin => round_robin(self.entrants.len()),
+                Format::Swiss { .. } => {
+                    let (pairings, bye) = swiss_pairings(&standings, &played, &byes);
+                    if let Some(bye) = bye.filter(|_| !pairings.is_empty()) {
+                        byes.insert(bye);
+                        standings[bye].byes += 1;
+                        standings[bye].points += 1.0;
+                    }
+                    pairings
+                },
+            };
+            if pairings.is_empty() {
+                break;
+            }
+            played.extend(pairings.iter().copied());
+            let round_matches = self.play_round(round, &pairings)?;
+            for m in &round_matches {
+                record(&mut standings, m);
+            }
+            matches.extend(round_matches);
+        }
+
+        let mut order: Vec<usize> = (0..standings.len()).collect();
+        order.sort_by(|a, b| {
+            let (a, b) = (&standings[*a], &standings[*b]);
+            b.points.total_cmp(&a.points).then(b.elo.total_cmp(&a.elo)).then(b.margin().total_cmp(&a.margin()))
+        });
+        Ok(TournamentReport {
+            settings: self.settings,
+            level: self.level.clone(),
+            format: self.format,
+            standings: order.into_iter().map(|i| standings[i].clone()).collect(),
+            matches,
+            elapsed: start.elapsed(),
+        })
+    }
+
+    // Plays every pairing of a round over all seeds and from both sides of the board
+    fn play_round(&self, round: usize, pairings: &[(usize, usize)]) -> Result<Vec<Match>, StrategyError> {
+        let mut games = vec![];
+        for &(a, b) in pairings {
+            for n in 0..self.games {
+                let seed = self.seed.wrapping_add((round * self.games + n) as u64);
+                games.push((a, b, seed));
+                games.push((b, a, seed));
+            }
+        }
+        simulate::in_parallel(self.threads, &games, |&(player, bot, seed)| {
+            let simulation = Simulation {
+                settings: self.settings,
+                level: self.level.clone(),
+                player: self.entrants[player].clone(),
+                bot: Some(self.entrants[bot].clone()),
+                games: 1,
+                seed,
+                max_ticks: self.max_ticks,
+                threads: 1,
+            };
+            Ok(Match { round, player, bot, result: simulation.play(seed)? })
+        })
+    }
+}
+
+// Every pair of entrants once, lower index first
+fn round_robin(entrants: usize) -> Vec<(usize, usize)> {
+    let mut pairings = vec![];
+    for a in 0..entrants {
+        for b in a + 1..entrants {
+            pairings.push((a, b));
+        }
+    }
+    pairings
+}
+
+// Pairs the entrants from the top of the table down, each with the best placed entrant it has not
+// met yet, or the best placed one left once it has met them all. With an odd number of entrants
+// the lowest placed one without a bye so far sits the round out, it is given back as the bye.
+fn swiss_pairings(standings: &[Standing], played: &HashSet<(usize, usize)>, byes: &HashSet<usize>) -> (Vec<(usize, usize)>, Option<usize>) {
+    let mut order: Vec<usize> = (0..standings.len()).collect();
+    order.sort_by(|a, b| standings[*b].points.total_cmp(&standings[*a].points).then(standings[*b].elo.total_cmp(&standings[*a].elo)));
+    let met = |a: usize, b: usize| played.contains(&(a.min(b), a.max(b)));
+
+    let mut bye = None;
+    if order.len() % 2 == 1 {
+        let i = order.iter().rposition(|a| !byes.contains(a)).unwrap_or(order.len() - 1);
+        bye = Some(order.remove(i));
+    }
+    let mut pairings = vec![];
+    while let Some(a) = order.first().copied() {
+        order.remove(0);
+        let i = order.iter().position(|b| !met(a, *b)).unwrap_or(0);
+        let b = order.remove(i);
+        pairings.push((a.min(b), a.max(b)));
+    }
+    (pairings, bye)
+}
+
+fn record(standings: &mut [Standing], m: &Match) {
+    let player = m.result.player;
+    let bot = m.result.bot.expect("tournament games are competitive");
+    let outcome = m.outcome();
+    let expected = 1.0 / (1.0 + 10f64.powf((standings[m.bot].elo - standings[m.player].elo) / 400.0));
+    let actual = match outcome {
+        Outcome::Win => 1.0,
+        Outcome::Loss => 0.0,
+        Outcome::Tie => 0.5,
+    };
+    let change = ELO_K * (actual - expected);
+
+    for (index, own, other, won, lost, elo) in [
+        (m.player, player.score, bot.score, outcome == Outcome::Win, outcome == Outcome::Loss, change),
+        (m.bot, bot.score, player.score, outcome == Outcome::Loss, outcome == Outcome::Win, -change),
+    ] {
+        let standing = &mut standings[index];
+        standing.games += 1;
+        standing.score_for += own as i64;
+        standing.score_against += other as i64;
+        standing.elo += elo;
+        if won {
+            standing.wins += 1;
+            standing.points += 1.0;
+        } else if lost {
+            standing.losses += 1;
+        } else {
+            standing.ties += 1;
+            standing.points += 0.5;
+        }
+    }
+}
+
+impl TournamentReport {
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("rank,name,games,wins,losses,ties,byes,points,elo,score_for,score_against,margin\n");
+        for (rank, s) in self.standings.iter().enumerate() {
+            csv.push_str(&format!(
+                "{},{},{},{},{},{},{},{},{:.1},{},{},{:.2}\n",
+                rank + 1,
+                csv_field(&s.name),
+                s.games,
+                s.wins,
+                s.losses,
+                s.ties,
+                s.byes,
+                s.points,
+                s.elo,
+                s.score_for,
+                s.score_against,
+                s.margin(),
+            ));
+        }
+        csv
+    }
+
+    pub fn to_json(&self) -> String {
+        #[derive(Serialize)]
+        struct Entry<'a> {
+            rank: usize,
+            #[serde(flatten)]
+            standing: &'a Standing,
+            margin: f64,
+        }
+        let entries: Vec<Entry> = self
+            .standings
+            .iter()
+            .enumerate()
+            .map(|(rank, standing)| Entry { rank: rank + 1, standing, margin: standing.margin() })
+            .collect();
+        serde_json::to_string_pretty(&entries).expect("standings always serialize")
+    }
+
+    pub fn save_csv(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, self.to_csv())
+    }
+
+    pub fn save_json(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, self.to_json())
+    }
+}
+
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+impl fmt::Display for TournamentReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let format = match self.format {
+            Format::RoundRobin => "Round robin".to_string(),
+            Format::Swiss { rounds } => format!("Swiss, {} rounds", rounds),
+        };
+        let board = match &self.level {
+            Some(level) => format!("{} ({}x{})", level.title, level.width, level.height),
+            None => format!("a {}x{} board", self.settings.grid_width, self.settings.grid_height),
+        };
+        writeln!(f, "{} on {}, {} games in {:.2}s", format, board, self.matches.len(), self.elapsed.as_secs_f64())?;
+        writeln!(f)?;
+        let width = self.standings.iter().map(|s| s.name.len()).max().unwrap_or(0).max(4);
+        // Byes only happen in Swiss rounds
+        let swiss = matches!(self.format, Format::Swiss { .. });
+        let byes = |byes: &dyn fmt::Display| if swiss { format!("  {:>4}", byes) } else { String::new() };
+        writeln!(f, "{:>4}  {:<width$}  {:>5}  {:>5}  {:>6}  {:>5}{}  {:>6}  {:>7}  {:>8}", "rank", "name", "games", "wins", "losses", "ties", byes(&"byes"), "points", "elo", "margin")?;
+        for (rank, s) in self.standings.iter().enumerate() {
+            writeln!(
+                f,
+                "{:>4}  {:<width$}  {:>5}  {:>5}  {:>6}  {:>5}{}  {:>6.1}  {:>7.1}  {:>+8.2}",
+                rank + 1,
+                s.name,
+                s.games,
+                s.wins,
+                s.losses,
+                s.ties,
+                byes(&s.byes),
+                s.points,
+                s.elo,
+                s.margin(),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_entrant_of_an_odd_swiss_gets_one_bye_worth_a_point() {
+        let tournament = Tournament {
+            settings: Settings { grid_width: 10, grid_height: 10, ..Settings::default() },
+            level: None,
+            entrants: vec![StrategyKind::Greedy, StrategyKind::Pathfinder, StrategyKind::Greedy],
+            format: Format::Swiss { rounds: 3 },
+            games: 1,
+            seed: 1,
+            max_ticks: 200,
+            threads: 1,
+        };
+        let report = tournament.run().unwrap();
+        assert_eq!(report.standings.len(), 3);
+        for s in &report.standings {
+            assert_eq!(s.byes, 1, "{}", s.name);
+            // Two opponents, two games against each
+            assert_eq!(s.games, 4, "{}", s.name);
+            assert_eq!(s.points, s.wins as f64 + s.ties as f64 / 2.0 + 1.0, "{}", s.name);
+        }
+        let pairings: HashSet<(usize, usize)> = report.matches.iter().map(|m| (m.player.min(m.bot), m.player.max(m.bot))).collect();
+        assert_eq!(pairings.len(), 3);
+    }
+
+    #[test]
+    fn swiss_pairings_play_a_rematch_instead_of_leaving_an_entrant_out() {
+        let standing = |points: f64| Standing {
+            name: String::new(),
+            games: 0,
+            wins: 0,
+            losses: 0,
+            ties: 0,
+            byes: 0,
+            points,
+            elo: START_ELO,
+            score_for: 0,
+            score_against: 0,
+        };
+        let standings = [standing(2.0), standing(1.0), standing(0.0), standing(1.0), standing(0.0)];
+        let played = HashSet::from([(0, 1), (0, 2), (0, 3), (0, 4)]);
+        let (pairings, bye) = swiss_pairings(&standings, &played, &HashSet::from([4]));
+        assert_eq!(bye, Some(2));
+        assert_eq!(pairings, vec![(0, 1), (3, 4)]);
+    }
+}